@@ -0,0 +1,40 @@
+use advent_of_code::bench::*;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+fn day15(c: &mut Criterion) {
+    let find_beacon = day15_find_beacon(&load_example(15), 20);
+    c.bench_function("day15 find_beacon", |b| b.iter(|| black_box(find_beacon())));
+
+    // The example is too small to tell the perimeter search and the row scan apart
+    let input = load_input(15);
+    let mut group = c.benchmark_group("day15 real bound");
+    group.sample_size(10);
+    let find_beacon = day15_find_beacon(&input, 4000000);
+    let scan_beacon = day15_scan_beacon(&input, 4000000);
+    assert_eq!(find_beacon(), scan_beacon());
+    group.bench_function("find_beacon_by_perimeter", |b| {
+        b.iter(|| black_box(find_beacon()))
+    });
+    group.bench_function("first_empty_spot", |b| b.iter(|| black_box(scan_beacon())));
+    group.finish();
+}
+
+fn day16(c: &mut Criterion) {
+    let release = day16_optimal_pressure_release(&load_example(16), 30);
+    c.bench_function("day16 optimal_pressure_release", |b| {
+        b.iter(|| black_box(release()))
+    });
+}
+
+fn day17(c: &mut Criterion) {
+    let drop_rocks = day17_drop_n_rocks(&load_example(17), 2022);
+    c.bench_function("day17 drop_n_rocks", |b| b.iter(|| black_box(drop_rocks())));
+}
+
+fn day19(c: &mut Criterion) {
+    let score = day19_score_blueprints(&load_example(19), 24, true);
+    c.bench_function("day19 score_blueprints", |b| b.iter(|| black_box(score())));
+}
+
+criterion_group!(benches, day15, day16, day17, day19);
+criterion_main!(benches);
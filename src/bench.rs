@@ -0,0 +1,63 @@
+//! Entry points for the criterion benchmarks in `benches/`, each running one expensive algorithm
+//! on an already parsed input
+
+use crate::{day15, day16, day17, day19};
+use itertools::Itertools;
+use std::fs;
+use std::path::Path;
+
+pub use crate::utils::load_example;
+
+/// Loads the puzzle input of `day` stored in `inputs/dayN.txt`, for the benchmarks that only make
+/// sense at the size of a real input
+pub fn load_input(day: u32) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("inputs/day{}.txt", day));
+    fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("could not load {}: {}", path.display(), err))
+}
+
+fn parse_sensors(input: &str) -> Vec<day15::Sensor> {
+    input
+        .lines()
+        .map(|x| x.parse::<day15::Sensor>().unwrap())
+        .collect_vec()
+}
+
+/// Tuning frequency of the only position in the square from 0 to `bound` no sensor covers
+pub fn day15_find_beacon(input: &str, bound: i64) -> impl Fn() -> Option<i64> {
+    let sensors = parse_sensors(input);
+    move || day15::find_beacon_by_perimeter(&sensors, bound).map(day15::calc_tuning_frequency)
+}
+
+/// Like `day15_find_beacon`, but scanning the square row by row
+pub fn day15_scan_beacon(input: &str, bound: i64) -> impl Fn() -> Option<i64> {
+    let sensors = parse_sensors(input);
+    move || {
+        day15::first_empty_spot(&sensors, 0..=bound, 0..=bound).map(day15::calc_tuning_frequency)
+    }
+}
+
+pub fn day16_optimal_pressure_release(input: &str, max_time: u64) -> impl Fn() -> u64 {
+    let graph = day16::parse_graph(input).unwrap();
+    move || graph.optimal_pressure_release(max_time)
+}
+
+/// Height of the tower after dropping `n` rocks one by one
+pub fn day17_drop_n_rocks(input: &str, n: usize) -> impl Fn() -> usize {
+    let rocks = day17::parse_rocks();
+    let movements = day17::parse_movements(input).unwrap();
+    move || {
+        let mut falling_rocks = day17::FallingRocks::new(7, &rocks, &movements);
+        falling_rocks.drop_n_rocks(n);
+        falling_rocks.current_height()
+    }
+}
+
+pub fn day19_score_blueprints(
+    input: &str,
+    max_time: usize,
+    with_quality: bool,
+) -> impl Fn() -> u64 {
+    let blueprints = day19::parse_blueprints(input);
+    move || day19::score_blueprints(&blueprints, max_time, with_quality)
+}
@@ -1,23 +1,117 @@
+use crate::days::DayResult;
+use crate::utils::top_k;
+use anyhow::Context;
 use itertools::Itertools;
 
-pub fn day1(content: String) -> anyhow::Result<()> {
-    println!("Day 1");
-    let groups = content.split('\n').group_by(|x| x.is_empty());
-    let value: usize = groups
+pub fn day1(content: String) -> anyhow::Result<DayResult> {
+    Ok(DayResult::new(part1(&content)?, Some(part2(&content)?)))
+}
+
+pub fn part1(content: &str) -> anyhow::Result<String> {
+    let (top_elf, max) = top_elf(content)?;
+    Ok(format!(
+        "Elf with most calories: {}\nMost calories: {}",
+        top_elf, max
+    ))
+}
+
+pub fn part2(content: &str) -> anyhow::Result<String> {
+    let (_, top_three) = day1_parts(content)?;
+    Ok(format!("Top three calories: {}", top_three))
+}
+
+/// Returns the calories carried by the top elf and the sum of the top three elves
+pub fn day1_parts(content: &str) -> anyhow::Result<(usize, usize)> {
+    let totals = top_k(elf_totals(content)?, 3);
+    let max = totals.first().copied().unwrap_or_default();
+    let top_three = totals.iter().sum();
+    Ok((max, top_three))
+}
+
+/// Returns the summed calories of each elf, in input order
+pub fn elf_totals(content: &str) -> anyhow::Result<Vec<usize>> {
+    // Trimming takes care of CRLF line endings and whitespace-only separator lines
+    let groups = content
+        .lines()
+        .map(str::trim)
+        .enumerate()
+        .group_by(|(_, x)| x.is_empty());
+    groups
         .into_iter()
-        .filter_map(|(is_empty, x)| {
-            if is_empty {
-                None
-            } else {
-                let sum: usize = x.map(|x| x.parse::<usize>().expect("not an int")).sum();
-                Some(sum)
-            }
+        .filter(|(is_empty, _)| !is_empty)
+        .map(|(_, group)| {
+            group
+                .map(|(i, x)| {
+                    x.parse::<usize>().with_context(|| {
+                        format!("could not parse calorie value '{}' on line {}", x, i + 1)
+                    })
+                })
+                .sum::<anyhow::Result<usize>>()
         })
-        .sorted()
-        .rev()
-        .take(3)
-        .sum();
-    println!("Most calories: {}", value);
-    println!();
-    Ok(())
+        .collect()
+}
+
+/// Returns the 1-based index of the elf carrying the most calories, together with its total
+pub fn top_elf(content: &str) -> anyhow::Result<(usize, usize)> {
+    let (index, total) = elf_totals(content)?
+        .into_iter()
+        .enumerate()
+        .max_by_key(|(_, total)| *total)
+        .context("no elves in input")?;
+    Ok((index + 1, total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = r#"1000
+2000
+3000
+
+4000
+
+5000
+6000
+
+7000
+8000
+9000
+
+10000"#;
+
+    #[test]
+    fn test_parts() {
+        assert_eq!(day1_parts(EXAMPLE).unwrap(), (24000, 45000));
+    }
+
+    #[test]
+    fn test_crlf_and_trailing_newline() {
+        let content = EXAMPLE.replace('\n', "\r\n") + "\r\n\r\n";
+        assert_eq!(day1_parts(&content).unwrap(), (24000, 45000));
+    }
+
+    #[test]
+    fn test_invalid_value() {
+        let error = day1_parts("1000\n2000\n\n12a3").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "could not parse calorie value '12a3' on line 4"
+        );
+    }
+
+    #[test]
+    fn test_top_elf() {
+        assert_eq!(
+            elf_totals(EXAMPLE).unwrap(),
+            vec![6000, 4000, 11000, 24000, 10000]
+        );
+        assert_eq!(top_elf(EXAMPLE).unwrap(), (4, 24000));
+    }
+
+    #[test]
+    fn test_day1_output() {
+        let output = day1(EXAMPLE.to_string()).unwrap();
+        assert!(output.part1.contains("Elf with most calories: 4"));
+    }
 }
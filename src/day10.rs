@@ -1,11 +1,16 @@
+use crate::days::DayResult;
 use anyhow::{anyhow, bail, Context};
 use itertools::Itertools;
 use std::cell::{Cell, RefCell};
+use std::fmt::Write;
 use std::rc::Rc;
 use std::str::FromStr;
 
 trait Watcher {
     fn watch_step(&mut self, vm: &VMState);
+
+    /// Called once an instruction has finished and its effect was applied to the register
+    fn watch_instruction_end(&mut self, _vm: &VMState) {}
 }
 
 struct VMState {
@@ -36,15 +41,19 @@ impl VM {
     }
 
     fn execute(&mut self, instruction: Instruction) {
+        for _ in 0..instruction.cycles() {
+            self.step();
+        }
+
         match instruction {
-            Instruction::Add(num) => {
-                self.step();
-                self.step();
-                self.state.register += num;
-            }
-            Instruction::Noop => {
-                self.step();
-            }
+            Instruction::Add(num) => self.state.register += num,
+            Instruction::Mul(num) => self.state.register *= num,
+            Instruction::Set(num) => self.state.register = num,
+            Instruction::Noop => {}
+        }
+
+        for watcher in self.watchers.iter_mut() {
+            watcher.watch_instruction_end(&self.state);
         }
     }
 
@@ -59,22 +68,42 @@ impl VM {
 
 enum Instruction {
     Add(i64),
+    Mul(i64),
+    Set(i64),
     Noop,
 }
 
+impl Instruction {
+    /// Number of cycles the instruction takes before its effect is applied
+    fn cycles(&self) -> usize {
+        match self {
+            Instruction::Add(_) => 2,
+            Instruction::Mul(_) => 3,
+            Instruction::Set(_) => 1,
+            Instruction::Noop => 1,
+        }
+    }
+}
+
 impl FromStr for Instruction {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let parts = s.split_whitespace().collect_vec();
         let op = *parts.first().ok_or_else(|| anyhow!("no operation"))?;
+        let argument = || {
+            parts
+                .get(1)
+                .ok_or_else(|| anyhow!("{} expected argument i64", op))
+                .and_then(|x| {
+                    x.parse::<i64>()
+                        .with_context(|| format!("{} expected argument i64", op))
+                })
+        };
         let instruction = match op {
-            "addx" => Self::Add(
-                parts
-                    .get(1)
-                    .ok_or_else(|| anyhow!("addx expected argument i64"))
-                    .and_then(|x| x.parse::<i64>().context("addx expected argument i64"))?,
-            ),
+            "addx" => Self::Add(argument()?),
+            "mulx" => Self::Mul(argument()?),
+            "setx" => Self::Set(argument()?),
             "noop" => Self::Noop,
             _ => bail!("unexpected op"),
         };
@@ -94,7 +123,7 @@ impl SignalStrengthWatcher {
 
 impl Watcher for SignalStrengthWatcher {
     fn watch_step(&mut self, vm: &VMState) {
-        if vm.program_counter < 20 || (vm.program_counter - 20) % 40 != 0 {
+        if vm.program_counter < 20 || !(vm.program_counter - 20).is_multiple_of(40) {
             return;
         }
 
@@ -112,22 +141,36 @@ impl Watcher for SignalStrengthWatcher {
 struct ScreenWatcher {
     screen: Rc<RefCell<String>>,
     x: i64,
+    width: usize,
+    sprite_half_width: i64,
 }
 
 impl ScreenWatcher {
     fn new(screen: Rc<RefCell<String>>) -> Self {
-        Self { screen, x: 0 }
+        Self::with_dimensions(40, 1, screen)
+    }
+
+    /// Screen with `width` pixels per row and a sprite covering `sprite_half_width` pixels on
+    /// each side of the register
+    fn with_dimensions(width: usize, sprite_half_width: i64, screen: Rc<RefCell<String>>) -> Self {
+        assert!(width > 0, "the screen needs at least one pixel per row");
+        Self {
+            screen,
+            x: 0,
+            width,
+            sprite_half_width,
+        }
     }
 }
 
 impl Watcher for ScreenWatcher {
     fn watch_step(&mut self, vm: &VMState) {
-        if vm.program_counter != 1 && vm.program_counter % 40 == 1 {
+        if vm.program_counter != 1 && (vm.program_counter - 1).is_multiple_of(self.width) {
             self.screen.borrow_mut().push('\n');
             self.x = 0;
         }
 
-        if (self.x - vm.register).abs() <= 1 {
+        if (self.x - vm.register).abs() <= self.sprite_half_width {
             self.screen.borrow_mut().push('#');
         } else {
             self.screen.borrow_mut().push('.');
@@ -144,9 +187,9 @@ impl Watcher for ScreenWatcher {
     }
 }
 
-pub fn day10(content: String) {
-    println!();
-    println!("==== Day 10 ====");
+pub fn day10(content: String) -> anyhow::Result<DayResult> {
+    let mut part1 = String::new();
+    let mut part2 = String::new();
     let program = content
         .lines()
         .map(|x| x.parse::<Instruction>().unwrap())
@@ -163,13 +206,12 @@ pub fn day10(content: String) {
 
     vm.run(program);
 
-    println!("Part 1");
-    println!("Total signal strength: {}", signal_strength.get());
+    writeln!(part1, "Total signal strength: {}", signal_strength.get())?;
+
+    writeln!(part2, "Screen:")?;
+    writeln!(part2, "{}", screen.borrow())?;
 
-    println!();
-    println!("Part 2");
-    println!("Screen:");
-    println!("{}", screen.borrow());
+    Ok(DayResult::new(part1, Some(part2)))
 }
 
 #[cfg(test)]
@@ -177,7 +219,7 @@ mod tests {
     use super::*;
     use std::ops::Deref;
 
-    const EXAMPLE: &'static str = r#"addx 15
+    const EXAMPLE: &str = r#"addx 15
 addx -11
 addx 6
 addx -3
@@ -365,4 +407,116 @@ noop"#;
 #######.......#######.......#######....."#
         );
     }
+
+    struct CycleWatcher {
+        cycles: Rc<Cell<usize>>,
+    }
+
+    impl Watcher for CycleWatcher {
+        fn watch_step(&mut self, vm: &VMState) {
+            self.cycles.set(vm.program_counter);
+        }
+    }
+
+    struct RegisterTraceWatcher {
+        trace: Rc<RefCell<Vec<i64>>>,
+    }
+
+    impl Watcher for RegisterTraceWatcher {
+        fn watch_step(&mut self, _vm: &VMState) {}
+
+        fn watch_instruction_end(&mut self, vm: &VMState) {
+            self.trace.borrow_mut().push(vm.register);
+        }
+    }
+
+    #[test]
+    fn test_instruction_end() {
+        let program = ["noop", "addx 3", "addx -5"]
+            .iter()
+            .map(|x| x.parse::<Instruction>().unwrap())
+            .collect_vec();
+
+        let trace = Rc::new(RefCell::new(vec![]));
+        let mut vm = VM::new();
+        vm.watchers.push(Box::new(RegisterTraceWatcher {
+            trace: trace.clone(),
+        }));
+
+        vm.run(program);
+
+        assert_eq!(trace.borrow().deref(), &[1, 4, -1]);
+    }
+
+    #[test]
+    fn test_extended_instructions() {
+        let program = ["setx 4", "mulx 3", "addx -2", "noop", "mulx -2"]
+            .iter()
+            .map(|x| x.parse::<Instruction>().unwrap())
+            .collect_vec();
+
+        let cycles = Rc::new(Cell::new(0));
+        let mut vm = VM::new();
+        vm.watchers.push(Box::new(CycleWatcher {
+            cycles: cycles.clone(),
+        }));
+
+        vm.run(program);
+
+        assert_eq!(vm.state.register, -20);
+        assert_eq!(cycles.get(), 1 + 3 + 2 + 1 + 3);
+    }
+
+    #[test]
+    fn test_invalid_argument() {
+        let err = "mulx two".parse::<Instruction>().err().unwrap();
+        assert_eq!(err.to_string(), "mulx expected argument i64");
+    }
+
+    #[test]
+    fn test_custom_dimensions() {
+        let program = "addx 4\n".to_owned() + &"noop\n".repeat(23);
+        let program = program
+            .lines()
+            .map(|x| x.parse::<Instruction>().unwrap())
+            .collect_vec();
+
+        let screen = Rc::new(RefCell::new(String::new()));
+        let screen_watcher = ScreenWatcher::with_dimensions(20, 1, screen.clone());
+        let mut vm = VM::new();
+        vm.watchers.push(Box::new(screen_watcher));
+
+        vm.run(program);
+
+        assert_eq!(screen.borrow().deref(), "##..###.............\n....#");
+    }
+
+    #[test]
+    fn test_single_column() {
+        let program = ["addx -3", "noop"]
+            .iter()
+            .map(|x| x.parse::<Instruction>().unwrap())
+            .collect_vec();
+
+        let screen = Rc::new(RefCell::new(String::new()));
+        let screen_watcher = ScreenWatcher::with_dimensions(1, 1, screen.clone());
+        let mut vm = VM::new();
+        vm.watchers.push(Box::new(screen_watcher));
+
+        vm.run(program);
+
+        assert_eq!(screen.borrow().deref(), "#\n#\n.");
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one pixel")]
+    fn test_zero_width() {
+        ScreenWatcher::with_dimensions(0, 1, Rc::new(RefCell::new(String::new())));
+    }
+
+    #[test]
+    fn test_day10_output() {
+        let output = day10(EXAMPLE.to_string()).unwrap();
+        assert!(output.part1.contains("Total signal strength: 13140"));
+    }
 }
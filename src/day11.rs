@@ -1,5 +1,8 @@
+use crate::days::DayResult;
+use crate::utils::top_k;
 use anyhow::{bail, Context};
 use itertools::Itertools;
+use std::fmt::Write;
 use std::str::FromStr;
 
 type Item = u64;
@@ -35,6 +38,9 @@ impl FromStr for Operand {
 #[derive(Debug, Clone, Copy)]
 enum Op {
     Add,
+    /// Worry levels are only known modulo the ring, so subtraction wraps around it like every
+    /// other operation, which keeps divisibility by every divisor correct
+    Sub,
     Mult,
 }
 
@@ -44,8 +50,9 @@ impl FromStr for Op {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let op = match s {
             "+" => Self::Add,
+            "-" => Self::Sub,
             "*" => Self::Mult,
-            _ => bail!("unsupported op"),
+            op => bail!("unsupported op '{}'", op),
         };
         Ok(op)
     }
@@ -59,16 +66,20 @@ struct Operation {
 }
 
 impl Operation {
+    /// Applies the operation modulo `ring`. The intermediate result is twice as wide as an
+    /// `Item`, so it can't overflow before the modulo, no matter how worried the monkeys are.
     fn calculate(&self, old: &Item, ring: &Item) -> Item {
-        let left = self.left.value(old);
-        let right = self.right.value(old);
+        let ring = *ring as u128;
+        let left = self.left.value(old) as u128;
+        let right = self.right.value(old) as u128;
 
         let result = match self.op {
             Op::Add => left + right,
+            Op::Sub => left + ring - right % ring,
             Op::Mult => left * right,
         };
 
-        result % ring
+        (result % ring) as Item
     }
 }
 
@@ -76,15 +87,18 @@ impl FromStr for Operation {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (left, op, right): (&str, &str, &str) = s
-            .split_whitespace()
-            .collect_tuple()
-            .context("invalid operation")?;
-        Ok(Self {
-            left: left.parse::<Operand>()?,
-            op: op.parse::<Op>()?,
-            right: right.parse::<Operand>()?,
-        })
+        let parse = || -> anyhow::Result<Self> {
+            let (left, op, right): (&str, &str, &str) = s
+                .split_whitespace()
+                .collect_tuple()
+                .context("expected 'left op right'")?;
+            Ok(Self {
+                left: left.parse::<Operand>()?,
+                op: op.parse::<Op>()?,
+                right: right.parse::<Operand>()?,
+            })
+        };
+        parse().with_context(|| format!("invalid operation '{}'", s))
     }
 }
 
@@ -97,7 +111,7 @@ struct Test {
 
 impl Test {
     fn target(&self, value: &Item) -> usize {
-        if value % self.divisor == 0 {
+        if value.is_multiple_of(self.divisor) {
             self.if_true
         } else {
             self.if_false
@@ -119,7 +133,7 @@ struct Monkey {
 }
 
 impl Monkey {
-    fn handle_items(&mut self, with_relief: bool, ring: &Item) -> Vec<(Item, usize)> {
+    fn handle_items(&mut self, relief_divisor: Option<Item>, ring: &Item) -> Vec<(Item, usize)> {
         let mut throws = vec![];
         for mut item in self.items.drain(..) {
             // Monkey inspects
@@ -127,8 +141,8 @@ impl Monkey {
             self.stats.inspections += 1;
 
             // Worry drains
-            if with_relief {
-                item /= 3;
+            if let Some(divisor) = relief_divisor {
+                item /= divisor;
             }
 
             // Test
@@ -200,40 +214,60 @@ struct MonkeyGroup {
 
 impl MonkeyGroup {
     fn new(monkeys: Vec<Monkey>) -> Self {
-        let ring = monkeys.iter().map(|x| x.test.divisor).product();
+        let divisors = monkeys.iter().map(|x| x.test.divisor).collect_vec();
+        let ring = compute_ring(&divisors);
         Self { monkeys, ring }
     }
 
-    fn n_rounds(&mut self, rounds: usize, with_relief: bool) {
+    /// Plays `rounds` rounds, dividing worry levels by `relief_divisor` after each inspection
+    fn n_rounds(&mut self, rounds: usize, relief_divisor: Option<Item>) {
         for _ in 0..rounds {
-            self.round(with_relief);
+            self.round(relief_divisor);
         }
     }
 
-    fn round(&mut self, with_relief: bool) {
+    fn round(&mut self, relief_divisor: Option<Item>) {
+        self.round_with_trace(relief_divisor);
+    }
+
+    /// Plays one round and returns a `(from, to)` pair for every thrown item
+    fn round_with_trace(&mut self, relief_divisor: Option<Item>) -> Vec<(usize, usize)> {
+        let mut trace = vec![];
         for i in 0..self.monkeys.len() {
             let monkey = self.monkeys.get_mut(i).unwrap();
-            for (item, target) in monkey.handle_items(with_relief, &self.ring) {
+            for (item, target) in monkey.handle_items(relief_divisor, &self.ring) {
                 self.monkeys
                     .get_mut(target)
                     .expect("unexpected monkey")
                     .items
                     .push(item);
+                trace.push((i, target));
             }
         }
+        trace
     }
 
     fn monkey_business(&self) -> usize {
-        self.monkeys
-            .iter()
-            .map(|x| x.stats.inspections)
-            .sorted()
-            .rev()
-            .take(2)
+        top_k(self.monkeys.iter().map(|x| x.stats.inspections), 2)
+            .into_iter()
             .product()
     }
 }
 
+/// Smallest modulus that preserves divisibility by all divisors (their least common multiple)
+fn compute_ring(divisors: &[Item]) -> Item {
+    divisors
+        .iter()
+        .fold(1, |ring, &divisor| ring / gcd(ring, divisor) * divisor)
+}
+
+fn gcd(mut a: Item, mut b: Item) -> Item {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
 impl FromStr for MonkeyGroup {
     type Err = anyhow::Error;
 
@@ -246,28 +280,27 @@ impl FromStr for MonkeyGroup {
     }
 }
 
-pub fn day11(content: String) {
-    println!();
-    println!("==== Day 11 ====");
-    let base_monkeys = content.parse::<MonkeyGroup>().unwrap();
+pub fn day11(content: String) -> anyhow::Result<DayResult> {
+    let mut part1 = String::new();
+    let mut part2 = String::new();
+    let base_monkeys = content.parse::<MonkeyGroup>()?;
 
-    println!("Part 1");
     let mut monkeys = base_monkeys.clone();
-    monkeys.n_rounds(20, true);
-    println!("Monkey business: {}", monkeys.monkey_business());
+    monkeys.n_rounds(20, Some(3));
+    writeln!(part1, "Monkey business: {}", monkeys.monkey_business())?;
 
-    println!();
-    println!("Part 2");
     let mut monkeys = base_monkeys;
-    monkeys.n_rounds(10000, false);
-    println!("Monkey business: {}", monkeys.monkey_business());
+    monkeys.n_rounds(10000, None);
+    writeln!(part2, "Monkey business: {}", monkeys.monkey_business())?;
+
+    Ok(DayResult::new(part1, Some(part2)))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    const EXAMPLE: &'static str = r#"Monkey 0:
+    const EXAMPLE: &str = r#"Monkey 0:
   Starting items: 79, 98
   Operation: new = old * 19
   Test: divisible by 23
@@ -298,14 +331,116 @@ Monkey 3:
     #[test]
     fn test_part_1() {
         let mut monkeys = EXAMPLE.parse::<MonkeyGroup>().unwrap();
-        monkeys.n_rounds(20, true);
+        monkeys.n_rounds(20, Some(3));
         assert_eq!(monkeys.monkey_business(), 10605);
     }
 
+    #[test]
+    fn test_relief_divisor() {
+        let mut monkeys = EXAMPLE.parse::<MonkeyGroup>().unwrap();
+        monkeys.n_rounds(5, Some(2));
+        // Inspections after 5 rounds are 23, 23, 3 and 26
+        assert_eq!(monkeys.monkey_business(), 598);
+    }
+
+    #[test]
+    fn test_round_with_trace() {
+        let mut monkeys = EXAMPLE.parse::<MonkeyGroup>().unwrap();
+        let trace = monkeys.round_with_trace(Some(3));
+
+        let mut throws = vec![vec![0; 4]; 4];
+        for (from, to) in trace {
+            throws[from][to] += 1;
+        }
+        assert_eq!(
+            throws,
+            vec![
+                vec![0, 0, 0, 2],
+                vec![4, 0, 0, 0],
+                vec![0, 1, 0, 2],
+                vec![0, 5, 0, 0]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_operation() {
+        let ring = 1000;
+        let sub = "old - 3".parse::<Operation>().unwrap();
+        assert_eq!(sub.calculate(&10, &ring), 7);
+        // 1001 is stored as 1, the result is still correct modulo the ring
+        assert_eq!(sub.calculate(&(1001 % ring), &ring), 998);
+        assert_eq!(sub.calculate(&2, &ring), 999);
+        let sub_large = "old - 2003".parse::<Operation>().unwrap();
+        assert_eq!(sub_large.calculate(&1, &ring), 998);
+
+        let square = "old * old".parse::<Operation>().unwrap();
+        assert_eq!(square.calculate(&12, &ring), 144);
+        assert_eq!(square.calculate(&40, &ring), 600);
+
+        let err = "old ? 2".parse::<Operation>().err().unwrap();
+        assert_eq!(
+            format!("{:#}", err),
+            "invalid operation 'old ? 2': unsupported op '?'"
+        );
+
+        let err = "old *".parse::<Operation>().err().unwrap();
+        assert_eq!(
+            format!("{:#}", err),
+            "invalid operation 'old *': expected 'left op right'"
+        );
+    }
+
+    #[test]
+    fn test_large_items() {
+        let monkey = r#"Monkey 0:
+  Starting items: 9223372036854775807
+  Operation: new = old * old
+  Test: divisible by 13
+    If true: throw to monkey 0
+    If false: throw to monkey 0"#;
+        let mut monkeys = monkey.parse::<MonkeyGroup>().unwrap();
+        assert_eq!(monkeys.monkeys[0].items, vec![u64::MAX / 2]);
+
+        // (2^63 - 1)^2 = 10 (mod 13), then 10^2 = 9 (mod 13)
+        monkeys.round(None);
+        assert_eq!(monkeys.monkeys[0].items, vec![10]);
+        monkeys.round(None);
+        assert_eq!(monkeys.monkeys[0].items, vec![9]);
+
+        let double = "old + old".parse::<Operation>().unwrap();
+        assert_eq!(double.calculate(&u64::MAX, &u64::MAX), 0);
+    }
+
     #[test]
     fn test_part_2() {
         let mut monkeys = EXAMPLE.parse::<MonkeyGroup>().unwrap();
-        monkeys.n_rounds(10000, false);
+        monkeys.n_rounds(10000, None);
         assert_eq!(monkeys.monkey_business(), 2713310158);
     }
+
+    #[test]
+    fn test_compute_ring() {
+        let divisors = [4, 6, 10, 15];
+        assert_eq!(compute_ring(&divisors), 60);
+
+        let mut lcm_ring = EXAMPLE.parse::<MonkeyGroup>().unwrap();
+        for (monkey, divisor) in lcm_ring.monkeys.iter_mut().zip(divisors) {
+            monkey.test.divisor = divisor;
+        }
+        lcm_ring.ring = compute_ring(&divisors);
+        let mut product_ring = lcm_ring.clone();
+        product_ring.ring = divisors.iter().product();
+        assert_eq!(product_ring.ring, 3600);
+
+        lcm_ring.n_rounds(1000, None);
+        product_ring.n_rounds(1000, None);
+        assert_eq!(lcm_ring.monkey_business(), product_ring.monkey_business());
+    }
+
+    #[test]
+    fn test_day11_output() {
+        let output = day11(EXAMPLE.to_string()).unwrap();
+        assert!(output.part1.contains("Monkey business: 10605"));
+    }
 }
@@ -1,6 +1,8 @@
+use crate::days::DayResult;
+use crate::utils::{Directions, Grid};
 use anyhow::{bail, Context};
-use na::Vector2;
-use pathfinding::prelude::astar;
+use pathfinding::prelude::{astar, bfs};
+use std::fmt::Write;
 use std::str::FromStr;
 
 type Point2 = na::Point2<i32>;
@@ -25,55 +27,69 @@ impl Tile {
 }
 
 struct Map {
-    tiles: Vec<Tile>,
-    width: usize,
-    length: usize,
+    tiles: Grid<Tile>,
     start_pos: Point2,
     target_pos: Point2,
 }
 
 impl Map {
-    fn neighbors(&self, pos: &Point2) -> Vec<Point2> {
+    /// Tiles reachable in one step when climbing at most `max_climb` levels at once
+    fn neighbors_with_climb(&self, pos: &Point2, max_climb: u32) -> Vec<Point2> {
         let Some(height) = self.height(pos) else {
             return vec![];
         };
-        [
-            pos + Vector2::new(1, 0),
-            pos + Vector2::new(-1, 0),
-            pos + Vector2::new(0, 1),
-            pos + Vector2::new(0, -1),
-        ]
-        .into_iter()
-        .filter(|pos| self.height(pos).unwrap_or(99) <= height + 1)
-        .collect()
+        self.tiles
+            .neighbors4(pos)
+            .into_iter()
+            .filter(|pos| self.height(pos).is_some_and(|x| x <= height + max_climb))
+            .collect()
     }
 
-    fn get(&self, pos: &Point2) -> Option<&Tile> {
-        let index = self.index(pos)?;
-        self.tiles.get(index)
+    /// Tiles from which `pos` can be reached in one step
+    fn reverse_neighbors(&self, pos: &Point2) -> Vec<Point2> {
+        let Some(height) = self.height(pos) else {
+            return vec![];
+        };
+        self.tiles
+            .neighbors4(pos)
+            .into_iter()
+            .filter(|pos| self.height(pos).is_some_and(|x| height <= x + 1))
+            .collect()
     }
 
-    fn height(&self, pos: &Point2) -> Option<u32> {
-        let index = self.index(pos)?;
-        self.tiles.get(index).map(|x| x.elevation())
+    /// Like `neighbors_with_climb` with a climb of one level, but stepping off an edge of the map
+    /// continues on the opposite edge
+    fn neighbors_wrapping(&self, pos: &Point2) -> Vec<Point2> {
+        let Some(height) = self.height(pos) else {
+            return vec![];
+        };
+        let width = self.tiles.width() as i32;
+        let length = self.tiles.height() as i32;
+        i32::DIRECTIONS_4
+            .into_iter()
+            .map(|dir| {
+                Point2::new(
+                    (pos.x + dir.x).rem_euclid(width),
+                    (pos.y + dir.y).rem_euclid(length),
+                )
+            })
+            .filter(|pos| self.height(pos).is_some_and(|x| x <= height + 1))
+            .collect()
     }
 
-    fn index(&self, pos: &Point2) -> Option<usize> {
-        if pos.x < 0 || pos.x >= self.width as i32 || pos.y < 0 || pos.y >= self.length as i32 {
-            return None;
-        }
-        Some((pos.x + pos.y * self.width as i32) as usize)
+    /// Manhattan distance when going around the edges of the map is allowed
+    fn wrapped_distance(&self, from: &Point2, to: &Point2) -> u32 {
+        let dx = from.x.abs_diff(to.x);
+        let dy = from.y.abs_diff(to.y);
+        dx.min(self.tiles.width() as u32 - dx) + dy.min(self.tiles.height() as u32 - dy)
     }
 
-    fn index_to_pos(&self, index: usize) -> Option<Point2> {
-        if index >= self.tiles.len() {
-            return None;
-        }
+    fn get(&self, pos: &Point2) -> Option<&Tile> {
+        self.tiles.get(pos)
+    }
 
-        Some(Point2::new(
-            (index % self.width) as i32,
-            (index / self.width) as i32,
-        ))
+    fn height(&self, pos: &Point2) -> Option<u32> {
+        self.get(pos).map(|x| x.elevation())
     }
 
     fn shortest_path_length_from_start(&self) -> usize {
@@ -81,27 +97,79 @@ impl Map {
     }
 
     fn shortest_path_length(&self, pos: &Point2) -> Option<usize> {
+        self.shortest_path_length_with_climb(pos, 1)
+    }
+
+    fn shortest_path_length_with_climb(&self, pos: &Point2, max_climb: u32) -> Option<usize> {
+        let path = self.path_with_climb(pos, max_climb)?;
+        Some(path.len() - 1)
+    }
+
+    /// Tiles along the shortest route from the start to the target, both included
+    #[allow(dead_code)]
+    fn shortest_path(&self) -> Option<Vec<Point2>> {
+        self.path_with_climb(&self.start_pos, 1)
+    }
+
+    fn path_with_climb(&self, pos: &Point2, max_climb: u32) -> Option<Vec<Point2>> {
         let (path, _) = astar(
             pos,
-            |pos| self.neighbors(pos).into_iter().map(|x| (x, 1 /* cost */)),
+            |pos| {
+                self.neighbors_with_climb(pos, max_climb)
+                    .into_iter()
+                    .map(|x| (x, 1 /* cost */))
+            },
             |pos| self.target_pos.x.abs_diff(pos.x) + self.target_pos.y.abs_diff(pos.y),
             |pos| self.get(pos).unwrap().is_target(),
         )?;
 
+        Some(path)
+    }
+
+    /// Like `shortest_path_length`, but on a map whose edges wrap around
+    #[allow(dead_code)]
+    fn shortest_path_length_wrapping(&self, pos: &Point2) -> Option<usize> {
+        let (path, _) = astar(
+            pos,
+            |pos| {
+                self.neighbors_wrapping(pos)
+                    .into_iter()
+                    .map(|x| (x, 1 /* cost */))
+            },
+            |pos| self.wrapped_distance(pos, &self.target_pos),
+            |pos| self.get(pos).unwrap().is_target(),
+        )?;
+
         Some(path.len() - 1)
     }
 
+    /// Searches from every lowest tile separately, see `shortest_from_any_lowest` for a faster
+    /// alternative
     #[allow(dead_code)]
     fn find_closest_start_point(&self) -> usize {
         self.tiles
+            .cells()
             .iter()
             .enumerate()
             .filter(|(_, x)| x.elevation() == 0)
-            .map(|(index, _)| self.index_to_pos(index).unwrap())
+            .map(|(index, _)| self.tiles.pos(index).unwrap())
             .filter_map(|pos| self.shortest_path_length(&pos))
             .min()
             .unwrap()
     }
+
+    /// Length of the shortest path from any lowest tile, found with a single backwards search
+    /// from the target
+    fn shortest_from_any_lowest(&self) -> usize {
+        let path = bfs(
+            &self.target_pos,
+            |pos| self.reverse_neighbors(pos),
+            |pos| self.height(pos) == Some(0),
+        )
+        .unwrap();
+
+        path.len() - 1
+    }
 }
 
 impl FromStr for Map {
@@ -113,10 +181,8 @@ impl FromStr for Map {
         let mut start_pos = Point2::origin();
         let mut target_pos = Point2::origin();
         let mut tiles = vec![];
-        let mut height = 0;
         let mut width = 0;
         for (y, line) in s.lines().enumerate() {
-            height += 1;
             if width == 0 {
                 width = line.len();
             } else if width != line.len() {
@@ -141,38 +207,44 @@ impl FromStr for Map {
             }
         }
 
+        if tiles.is_empty() {
+            bail!("empty map");
+        }
+
         Ok(Map {
-            tiles,
-            length: height,
-            width,
+            tiles: Grid::new(tiles, width),
             start_pos,
             target_pos,
         })
     }
 }
 
-pub fn day12(content: String) {
-    println!();
-    println!("==== Day 12 ====");
-    let map = content.parse::<Map>().unwrap();
+pub fn day12(content: String) -> anyhow::Result<DayResult> {
+    let mut part1 = String::new();
+    let mut part2 = String::new();
+    let map = content.parse::<Map>()?;
 
-    println!("Part 1");
-    println!(
+    writeln!(
+        part1,
         "Shortest path length: {}",
         map.shortest_path_length_from_start()
-    );
+    )?;
+
+    writeln!(
+        part2,
+        "Shortest path length: {}",
+        map.shortest_from_any_lowest()
+    )?;
 
-    println!();
-    println!("Part 2");
-    println!("Ignoring part two to safe time");
-    // println!("Shortest path length: {}", map.find_closest_start_point());
+    Ok(DayResult::new(part1, Some(part2)))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use itertools::Itertools;
 
-    const EXAMPLE: &'static str = r#"Sabqponm
+    const EXAMPLE: &str = r#"Sabqponm
 abcryxxl
 accszExk
 acctuvwj
@@ -189,4 +261,75 @@ abdefghi"#;
         let map = EXAMPLE.parse::<Map>().unwrap();
         assert_eq!(map.find_closest_start_point(), 29);
     }
+
+    #[test]
+    fn test_shortest_from_any_lowest() {
+        let map = EXAMPLE.parse::<Map>().unwrap();
+        assert_eq!(map.shortest_from_any_lowest(), 29);
+        assert_eq!(
+            map.shortest_from_any_lowest(),
+            map.find_closest_start_point()
+        );
+    }
+
+    #[test]
+    fn test_max_climb() {
+        let map = EXAMPLE.parse::<Map>().unwrap();
+        let start = map.start_pos;
+        assert_eq!(map.shortest_path_length_with_climb(&start, 1), Some(31));
+
+        let steeper = map.shortest_path_length_with_climb(&start, 2).unwrap();
+        assert!(steeper < 31);
+        assert_eq!(steeper, 27);
+    }
+
+    #[test]
+    fn test_wrapping() {
+        // Climbs to the right along the first row, the target is right next to the top when
+        // wrapping around, but otherwise at the other end of the second row
+        let map = format!("Sabcdefghijklmnopqrstuvwxyz\nE{}", "z".repeat(26))
+            .parse::<Map>()
+            .unwrap();
+        let start = map.start_pos;
+        assert_eq!(map.shortest_path_length(&start), Some(51));
+        assert_eq!(map.shortest_path_length_wrapping(&start), Some(28));
+
+        assert_eq!(
+            map.neighbors_wrapping(&Point2::new(26, 1)),
+            vec![
+                Point2::new(0, 1),
+                Point2::new(25, 1),
+                Point2::new(26, 0),
+                Point2::new(26, 0)
+            ]
+        );
+        assert_eq!(
+            map.wrapped_distance(&Point2::new(26, 1), &map.target_pos),
+            1
+        );
+
+        // The example has a shortcut around its edges as well
+        let map = EXAMPLE.parse::<Map>().unwrap();
+        assert_eq!(map.shortest_path_length_wrapping(&map.start_pos), Some(30));
+    }
+
+    #[test]
+    fn test_shortest_path() {
+        let map = EXAMPLE.parse::<Map>().unwrap();
+        let path = map.shortest_path().unwrap();
+        assert_eq!(path.len(), 32);
+        assert_eq!(path.first(), Some(&map.start_pos));
+        assert_eq!(path.last(), Some(&map.target_pos));
+
+        for (from, to) in path.iter().tuple_windows() {
+            assert_eq!((to - from).abs().sum(), 1);
+            assert!(map.height(to).unwrap() <= map.height(from).unwrap() + 1);
+        }
+    }
+
+    #[test]
+    fn test_day12_output() {
+        let output = day12(EXAMPLE.to_string()).unwrap();
+        assert!(output.part1.contains("Shortest path length: 31"));
+    }
 }
@@ -1,10 +1,12 @@
-use anyhow::Context;
+use crate::days::DayResult;
+use anyhow::{bail, Context};
 use itertools::Itertools;
 use nom::branch::alt;
 use nom::character::complete::{char, u64};
 use nom::multi::separated_list0;
 use nom::{Finish, IResult};
 use std::cmp::Ordering;
+use std::fmt::Write;
 use std::str::FromStr;
 
 #[derive(Debug)]
@@ -25,12 +27,15 @@ impl FromStr for SignalPair {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let signals = s
             .lines()
-            .map(|x| x.parse::<Signal>().context("could not parse signal"))
+            .map(|x| {
+                x.parse::<Signal>()
+                    .with_context(|| format!("could not parse signal '{}'", x))
+            })
             .collect::<anyhow::Result<Vec<Signal>>>()?;
         let (left, right) = signals
             .into_iter()
             .collect_tuple()
-            .expect("unexpected length");
+            .context("a signal pair must consist of exactly two lines")?;
         Ok(SignalPair { left, right })
     }
 }
@@ -105,36 +110,192 @@ impl FromStr for Signal {
     }
 }
 
-fn signal_order_value(pairs: &[SignalPair]) -> usize {
-    pairs
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Num(u64),
+    End,
+    /// A byte that can't appear at this point of a signal, or `None` if it ends too early
+    Invalid(Option<u8>),
+}
+
+/// What the cursor accepts next, so that commas only ever separate two list items
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Expect {
+    /// A number or a list, at the very start or right after a comma
+    Item,
+    /// An item or the closing bracket, right after an opening bracket
+    ItemOrClose,
+    /// A comma or the closing bracket, right after an item
+    CommaOrClose,
+}
+
+/// Reads the tokens of a signal one by one, able to pretend that a number is wrapped in lists
+struct Cursor<'a> {
+    input: &'a [u8],
+    pos: usize,
+    /// Lists opened in the input and not closed yet
+    depth: usize,
+    expect: Expect,
+    /// Lists the next number is pretended to be wrapped in
+    wraps: usize,
+    /// Closing brackets of those lists still to be returned
+    closes: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            input: input.as_bytes(),
+            pos: 0,
+            depth: 0,
+            expect: Expect::Item,
+            wraps: 0,
+            closes: 0,
+        }
+    }
+
+    fn peek(&mut self) -> Token {
+        if self.closes > 0 {
+            return Token::Close;
+        }
+
+        while self
+            .input
+            .get(self.pos)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.pos += 1;
+        }
+        let x = self.input.get(self.pos).copied();
+        match (x, self.expect) {
+            (None, _) if self.depth > 0 || self.expect == Expect::Item => Token::Invalid(None),
+            (None, _) => Token::End,
+            (Some(b','), Expect::CommaOrClose) if self.depth > 0 => {
+                self.pos += 1;
+                self.expect = Expect::Item;
+                self.peek()
+            }
+            (Some(b']'), Expect::ItemOrClose | Expect::CommaOrClose) if self.depth > 0 => {
+                Token::Close
+            }
+            (Some(b'['), Expect::Item | Expect::ItemOrClose) => Token::Open,
+            (Some(x), Expect::Item | Expect::ItemOrClose) if x.is_ascii_digit() => {
+                let value = self.input[self.pos..]
+                    .iter()
+                    .take_while(|x| x.is_ascii_digit())
+                    .fold(0, |value, x| value * 10 + (x - b'0') as u64);
+                Token::Num(value)
+            }
+            (x, _) => Token::Invalid(x),
+        }
+    }
+
+    fn advance(&mut self) {
+        if self.closes > 0 {
+            self.closes -= 1;
+            return;
+        }
+
+        match self.peek() {
+            Token::Num(_) => {
+                while self.input.get(self.pos).is_some_and(u8::is_ascii_digit) {
+                    self.pos += 1;
+                }
+                self.closes = self.wraps;
+                self.wraps = 0;
+                self.expect = Expect::CommaOrClose;
+            }
+            Token::Open => {
+                self.pos += 1;
+                self.depth += 1;
+                self.expect = Expect::ItemOrClose;
+            }
+            Token::Close => {
+                self.pos += 1;
+                self.depth -= 1;
+                self.expect = Expect::CommaOrClose;
+            }
+            Token::End | Token::Invalid(_) => {}
+        }
+    }
+}
+
+/// Compares two well-formed signals like `Signal::cmp`, but reads them token by token instead of
+/// parsing them first. Whitespace is skipped, while unexpected bytes, misplaced commas and
+/// unbalanced brackets are an error. Input past the point where the order is decided isn't read.
+fn compare_raw(left: &str, right: &str) -> anyhow::Result<Ordering> {
+    let mut left = Cursor::new(left);
+    let mut right = Cursor::new(right);
+    loop {
+        let ordering = match (left.peek(), right.peek()) {
+            (Token::Invalid(Some(x)), _) | (_, Token::Invalid(Some(x))) => {
+                bail!("unexpected '{}' in signal", x.escape_ascii())
+            }
+            (Token::Invalid(None), _) | (_, Token::Invalid(None)) => {
+                bail!("signal ends before all of its lists are closed")
+            }
+            (Token::End, Token::End) => Ordering::Equal,
+            (Token::Num(l), Token::Num(r)) if l != r => l.cmp(&r),
+            (l, r) if l == r => {
+                left.advance();
+                right.advance();
+                continue;
+            }
+            // The left list ran out of items first
+            (Token::Close | Token::End, _) => Ordering::Less,
+            (_, Token::Close | Token::End) => Ordering::Greater,
+            // A number compared to a list is wrapped in a list itself
+            (Token::Open, Token::Num(_)) => {
+                left.advance();
+                right.wraps += 1;
+                continue;
+            }
+            (Token::Num(_), Token::Open) => {
+                left.wraps += 1;
+                right.advance();
+                continue;
+            }
+            (l, r) => unreachable!("{:?} and {:?} were handled above", l, r),
+        };
+        return Ok(ordering);
+    }
+}
+
+fn signal_order_value(content: &str) -> anyhow::Result<usize> {
+    let pairs = content
+        .split("\n\n")
+        .enumerate()
+        .map(|(i, x)| {
+            x.parse::<SignalPair>()
+                .with_context(|| format!("invalid signal pair {}", i + 1))
+        })
+        .collect::<anyhow::Result<Vec<SignalPair>>>()?;
+
+    Ok(pairs
         .iter()
         .enumerate()
         .filter(|(_, pair)| pair.check_order())
         .map(|(i, _)| i + 1)
-        .sum()
+        .sum())
 }
 
-pub fn day13(content: String) {
-    println!();
-    println!("==== Day 13 ====");
+pub fn day13(content: String) -> anyhow::Result<DayResult> {
+    let mut part1 = String::new();
+    let mut part2 = String::new();
 
-    println!("Part 1");
-    let signal_pairs = content
-        .split("\n\n")
-        .map(|x| x.parse::<SignalPair>().unwrap())
-        .collect_vec();
-    println!("Signal order value: {}", signal_order_value(&signal_pairs));
-
-    println!();
-    println!("Part 2");
-    let signals = content
-        .lines()
-        .filter(|x| !x.is_empty())
-        .map(|x| x.parse::<Signal>().unwrap())
-        .chain(create_divider_packets())
-        .sorted()
-        .collect_vec();
-    println!("Signal decoder key: {}", find_decoder_key(&signals));
+    writeln!(
+        part1,
+        "Signal order value: {}",
+        signal_order_value(&content)?
+    )?;
+
+    // Part 1 already made sure that every signal is well-formed
+    let signals = content.lines().filter(|x| !x.is_empty()).collect_vec();
+    writeln!(part2, "Signal decoder key: {}", decoder_key_raw(&signals)?)?;
+
+    Ok(DayResult::new(part1, Some(part2)))
 }
 
 fn create_divider_packets() -> [Signal; 2] {
@@ -144,6 +305,8 @@ fn create_divider_packets() -> [Signal; 2] {
     ]
 }
 
+/// Decoder key of a sorted list that already contains the divider packets
+#[allow(dead_code)]
 fn find_decoder_key(list: &[Signal]) -> usize {
     let dividers = create_divider_packets();
     list.iter()
@@ -153,46 +316,50 @@ fn find_decoder_key(list: &[Signal]) -> usize {
         .product()
 }
 
+/// Decoder key of the signals without the divider packets, found by counting the signals that
+/// sort before each divider instead of sorting the whole list
 #[cfg(test)]
-mod tests {
-    use super::*;
-
-    const EXAMPLE: &'static str = r#"[1,1,3,1,1]
-[1,1,5,1,1]
-
-[[1],[2,3,4]]
-[[1],4]
-
-[9]
-[[8,7,6]]
-
-[[4,4],4,4]
-[[4,4],4,4,4]
-
-[7,7,7,7]
-[7,7,7]
-
-[]
-[3]
+fn decoder_key(signals: &[Signal]) -> usize {
+    create_divider_packets()
+        .iter()
+        .enumerate()
+        .map(|(i, divider)| {
+            // Earlier dividers sort before this one
+            let smaller = signals.iter().filter(|x| *x < divider).count();
+            smaller + i + 1
+        })
+        .product()
+}
 
-[[[]]]
-[[]]
+/// Like `decoder_key`, but compares the unparsed signals to the dividers
+fn decoder_key_raw(signals: &[&str]) -> anyhow::Result<usize> {
+    let mut key = 1;
+    for (i, divider) in ["[[2]]", "[[6]]"].iter().enumerate() {
+        let mut smaller = 0;
+        for signal in signals {
+            if compare_raw(signal, divider)? == Ordering::Less {
+                smaller += 1;
+            }
+        }
+        // Earlier dividers sort before this one
+        key *= smaller + i + 1;
+    }
+    Ok(key)
+}
 
-[1,[2,[3,[4,[5,6,7]]]],8,9]
-[1,[2,[3,[4,[5,6,0]]]],8,9]"#;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::load_example;
 
     #[test]
     fn test_part_1() {
-        let signal_pairs = EXAMPLE
-            .split("\n\n")
-            .map(|x| x.parse::<SignalPair>().unwrap())
-            .collect_vec();
-        assert_eq!(signal_order_value(&signal_pairs), 13);
+        assert_eq!(signal_order_value(&load_example(13)).unwrap(), 13);
     }
 
     #[test]
     fn test_part_2() {
-        let signals = EXAMPLE
+        let signals = load_example(13)
             .lines()
             .filter(|x| !x.is_empty())
             .map(|x| x.parse::<Signal>().unwrap())
@@ -201,4 +368,153 @@ mod tests {
             .collect_vec();
         assert_eq!(find_decoder_key(&signals), 140);
     }
+
+    #[test]
+    fn test_malformed_signal() {
+        let err = signal_order_value("[1,1]\n[2]\n\n[1,2\n[3]").unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(message.starts_with("invalid signal pair 2: could not parse signal '[1,2'"));
+
+        let err = "[1]\n[2]\n[3]".parse::<SignalPair>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "a signal pair must consist of exactly two lines"
+        );
+    }
+
+    #[test]
+    fn test_decoder_key() {
+        let signals = load_example(13)
+            .lines()
+            .filter(|x| !x.is_empty())
+            .map(|x| x.parse::<Signal>().unwrap())
+            .collect_vec();
+        assert_eq!(decoder_key(&signals), 140);
+
+        let sorted = signals
+            .into_iter()
+            .chain(create_divider_packets())
+            .sorted()
+            .collect_vec();
+        assert_eq!(find_decoder_key(&sorted), 140);
+    }
+
+    #[test]
+    fn test_compare_raw() {
+        let signals = load_example(13)
+            .lines()
+            .filter(|x| !x.is_empty())
+            .map(str::to_owned)
+            .collect_vec();
+        for (left, right) in signals.iter().tuple_combinations() {
+            let expected = left
+                .parse::<Signal>()
+                .unwrap()
+                .cmp(&right.parse::<Signal>().unwrap());
+            let actual = compare_raw(left, right).unwrap();
+            assert_eq!(actual, expected, "{} vs {}", left, right);
+        }
+
+        assert_eq!(
+            compare_raw("[[1],[2,3,4]]", "[[1],4]").unwrap(),
+            Ordering::Less
+        );
+        assert_eq!(compare_raw("[[[10]]]", "[10]").unwrap(), Ordering::Equal);
+        assert_eq!(compare_raw("[]", "[[]]").unwrap(), Ordering::Less);
+    }
+
+    #[test]
+    fn test_compare_raw_unexpected_bytes() {
+        assert_eq!(compare_raw("[1, 2]", "[1, 3]").unwrap(), Ordering::Less);
+        assert_eq!(compare_raw("[1,2]\r", "[1,2]").unwrap(), Ordering::Equal);
+
+        let err = compare_raw("[1,x]", "[1,3]").unwrap_err();
+        assert_eq!(err.to_string(), "unexpected 'x' in signal");
+        let err = compare_raw("[1]", "[-1]").unwrap_err();
+        assert_eq!(err.to_string(), "unexpected '-' in signal");
+    }
+
+    #[test]
+    fn test_compare_raw_malformed() {
+        let unexpected = |left, right, byte| {
+            let err = compare_raw(left, right).unwrap_err();
+            assert_eq!(err.to_string(), format!("unexpected '{}' in signal", byte));
+        };
+        unexpected("[1,,2]", "[1,2]", ',');
+        unexpected("[,1]", "[1]", ',');
+        unexpected("[1,]", "[1]", ']');
+        unexpected("[1 2]", "[1,2]", '2');
+        unexpected("[[1]2]", "[[1],2]", '2');
+        unexpected("[1],[2]", "[1]", ',');
+        unexpected("[1][2]", "[1]", '[');
+        unexpected("[1]]", "[1]", ']');
+        unexpected("[1]", "]", ']');
+
+        let err = compare_raw("[1,[2]", "[1,[2]").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "signal ends before all of its lists are closed"
+        );
+        assert!(compare_raw("", "[]").is_err());
+    }
+
+    #[test]
+    fn test_decoder_key_raw() {
+        let example = load_example(13);
+        let signals = example.lines().filter(|x| !x.is_empty()).collect_vec();
+        assert_eq!(decoder_key_raw(&signals).unwrap(), 140);
+    }
+
+    /// Xorshift, good enough to generate test data
+    fn next_random(seed: &mut u64, max: u64) -> u64 {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 7;
+        *seed ^= *seed << 17;
+        *seed % max
+    }
+
+    /// Random list of numbers and lists up to `depth` levels deep
+    fn random_signal(seed: &mut u64, depth: usize) -> String {
+        let mut items = vec![];
+        for _ in 0..next_random(seed, 4) {
+            if depth > 0 && next_random(seed, 3) == 0 {
+                items.push(random_signal(seed, depth - 1));
+            } else {
+                // Few distinct numbers, so that comparisons often go deep
+                items.push(next_random(seed, 4).to_string());
+            }
+        }
+        format!("[{}]", items.join(","))
+    }
+
+    #[test]
+    fn test_compare_raw_random() {
+        let mut seed = 0x2545f4914f6cdd1d;
+        let mut orders = [0; 3];
+        for _ in 0..5000 {
+            let left = random_signal(&mut seed, 3);
+            let right = random_signal(&mut seed, 3);
+            let expected = left
+                .parse::<Signal>()
+                .unwrap()
+                .cmp(&right.parse::<Signal>().unwrap());
+            assert_eq!(
+                compare_raw(&left, &right).unwrap(),
+                expected,
+                "{} vs {}",
+                left,
+                right
+            );
+            orders[(expected as i8 + 1) as usize] += 1;
+        }
+
+        // Every outcome was covered
+        assert!(orders.iter().all(|&x| x > 100), "{:?}", orders);
+    }
+
+    #[test]
+    fn test_day13_output() {
+        let output = day13(load_example(13)).unwrap();
+        assert!(output.part1.contains("Signal order value: 13"));
+    }
 }
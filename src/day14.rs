@@ -1,6 +1,8 @@
+use crate::days::DayResult;
+use crate::utils::Directions;
 use anyhow::Context;
 use itertools::Itertools;
-use std::collections::HashMap;
+use std::fmt::Write;
 use std::str::FromStr;
 
 type Point2 = na::Point2<i64>;
@@ -24,65 +26,149 @@ impl Tile {
     }
 }
 
+/// What happened to a grain of sand dropped into the map
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum SandOutcome {
+    Settled,
+    /// Still falling after the tick limit, so it fell past every wall
+    FellOff,
+    /// The tile it was dropped at already holds sand or a wall
+    SourceBlocked,
+}
+
+/// Sand is always poured in at this column
+const SOURCE_X: i64 = 500;
+
+/// Directions a grain tries to fall in, in order of preference: down, down left and down right
+const FALL_DIRECTIONS: [Vector2; 3] = [
+    i64::DIRECTIONS_4[2],
+    i64::DIRECTIONS_8[6],
+    i64::DIRECTIONS_8[4],
+];
+
 struct Map {
-    tiles: HashMap<Point2, Tile>,
+    tiles: Vec<Tile>,
+    min_x: i64,
+    width: usize,
     max_y: i64,
     has_floor: bool,
 }
 
 impl Map {
     fn from_paths(s: &str, has_floor: bool) -> Self {
-        let mut tiles = HashMap::new();
-
-        let mut max_y = 0;
-        for path in s.lines() {
-            let path = path.parse::<Path>().unwrap();
-            for point in path.iter() {
-                max_y = max_y.max(point.y);
-                tiles.insert(point, Tile::Wall);
-            }
-        }
-
-        Self {
-            tiles,
+        let walls = parse_walls(s).unwrap();
+        let max_y = walls.iter().map(|x| x.y).max().unwrap_or(0);
+
+        // Sand falling from the source can spread at most one column per row, so the grid covers
+        // that triangle down to the floor and any wall outside of it
+        let floor = max_y + 2;
+        let (min_x, max_x) = walls
+            .iter()
+            .map(|x| x.x)
+            .chain([SOURCE_X - floor, SOURCE_X + floor])
+            .minmax()
+            .into_option()
+            .unwrap();
+        let width = (max_x - min_x + 1) as usize;
+
+        let mut map = Self {
+            tiles: vec![Tile::Air; width * floor as usize],
+            min_x,
+            width,
             has_floor,
             max_y,
+        };
+        for wall in walls {
+            map.set(wall, Tile::Wall);
         }
+        map
     }
 
-    fn add_sand(&mut self, mut pos: Point2, max_settle_ticks: usize) -> bool {
+    /// Drops a single grain from `pos`, it falls off if it doesn't settle within
+    /// `max_settle_ticks`
+    #[allow(dead_code)]
+    fn add_sand(&mut self, pos: Point2, max_settle_ticks: usize) -> SandOutcome {
         if self.get(pos) != Tile::Air {
-            return false;
+            return SandOutcome::SourceBlocked;
         }
 
-        self.set(pos, Tile::Sand);
+        match self.add_sand_traced(pos, max_settle_ticks) {
+            Some(_) => SandOutcome::Settled,
+            None => SandOutcome::FellOff,
+        }
+    }
 
-        const DIRS: [Vector2; 3] = [Vector2::new(0, 1), Vector2::new(-1, 1), Vector2::new(1, 1)];
+    /// Like `add_sand`, but returns every tile the grain passed through, ending where it settled
+    #[allow(dead_code)]
+    fn add_sand_traced(&mut self, pos: Point2, max_settle_ticks: usize) -> Option<Vec<Point2>> {
+        if self.get(pos) != Tile::Air {
+            return None;
+        }
+
+        let mut path = vec![pos];
         'update: for _ in 0..max_settle_ticks {
-            for dir in DIRS {
-                let target = pos + dir;
+            let current = *path.last().unwrap();
+            for dir in FALL_DIRECTIONS {
+                let target = current + dir;
                 if self.get(target) == Tile::Air {
-                    self.set(pos, Tile::Air);
-                    self.set(target, Tile::Sand);
-                    pos = target;
+                    path.push(target);
                     continue 'update;
                 }
             }
 
             // No position was found
-            return true;
+            self.set(current, Tile::Sand);
+            return Some(path);
         }
 
         // Ran out of time
-        false
+        None
     }
 
+    /// Drops grains from `pos` until one is blocked or doesn't settle within `max_settle_ticks`,
+    /// returns how many settled
     fn fill_sand(&mut self, pos: Point2, max_settle_ticks: usize) -> usize {
+        let (counter, _) = self.fill_sand_with_outcome(pos, max_settle_ticks);
+        counter
+    }
+
+    /// Like `fill_sand`, but also returns why the last grain didn't settle.
+    ///
+    /// Every grain follows the path of the previous one up to the tile right above where it
+    /// settled, so the next grain resumes from there instead of starting at the source.
+    fn fill_sand_with_outcome(
+        &mut self,
+        pos: Point2,
+        max_settle_ticks: usize,
+    ) -> (usize, SandOutcome) {
+        if self.get(pos) != Tile::Air {
+            return (0, SandOutcome::SourceBlocked);
+        }
+
         let mut counter = 0;
-        while self.add_sand(pos, max_settle_ticks) {
+        let mut path = vec![pos];
+        'fall: while let Some(&current) = path.last() {
+            // Ran out of time
+            if path.len() > max_settle_ticks {
+                return (counter, SandOutcome::FellOff);
+            }
+
+            for dir in FALL_DIRECTIONS {
+                let target = current + dir;
+                if self.get(target) == Tile::Air {
+                    path.push(target);
+                    continue 'fall;
+                }
+            }
+
+            // No position was found, the grain settles
+            self.set(current, Tile::Sand);
+            path.pop();
             counter += 1;
         }
-        counter
+
+        // The last grain settled at the source
+        (counter, SandOutcome::SourceBlocked)
     }
 
     fn get(&self, pos: Point2) -> Tile {
@@ -90,15 +176,30 @@ impl Map {
             return Tile::Wall;
         }
 
-        self.tiles.get(&pos).copied().unwrap_or(Tile::Air)
+        self.index(pos)
+            .map(|index| self.tiles[index])
+            .unwrap_or(Tile::Air)
     }
 
+    /// Tiles outside the grid can't hold settled sand, so writes to them are dropped
     fn set(&mut self, pos: Point2, tile: Tile) -> Tile {
         let old = self.get(pos);
-        self.tiles.insert(pos, tile);
+        if let Some(index) = self.index(pos) {
+            self.tiles[index] = tile;
+        }
         old
     }
 
+    fn index(&self, pos: Point2) -> Option<usize> {
+        let x = pos.x - self.min_x;
+        if x < 0 || x >= self.width as i64 || pos.y < 0 {
+            return None;
+        }
+
+        let index = x as usize + pos.y as usize * self.width;
+        (index < self.tiles.len()).then_some(index)
+    }
+
     #[allow(dead_code)]
     fn print_map(&self, x_start: i64, x_end: i64, y_start: i64, y_end: i64) {
         let mut s = String::new();
@@ -112,12 +213,66 @@ impl Map {
     }
 }
 
+/// Every tile covered by the wall paths, one path per line
+fn parse_walls(s: &str) -> anyhow::Result<Vec<Point2>> {
+    let mut walls = vec![];
+    for path in s.lines() {
+        walls.extend(path.parse::<Path>()?.iter());
+    }
+    Ok(walls)
+}
+
+/// Counts the grains that settle from `pos` until it is blocked, with a floor two rows below the
+/// lowest wall. Instead of building a `Map`, only the current row of the cone below `pos` is kept
+/// in memory, at an offset to the leftmost column the sand can reach.
+///
+/// With a floor every reachable tile eventually fills up, so a tile holds sand exactly if it
+/// isn't a wall and one of the three tiles above it holds sand.
+fn fill_sand_bounded(walls: &[Point2], pos: Point2) -> usize {
+    if walls.contains(&pos) {
+        return 0;
+    }
+
+    // The cone widens by one tile in each direction per row
+    let floor = walls.iter().map(|x| x.y).max().unwrap_or(0) + 2;
+    let offset = pos.x - (floor - pos.y);
+    let width = (2 * (floor - pos.y) + 1) as usize;
+
+    // Walls are visited row by row as the cone moves down
+    let mut walls = walls
+        .iter()
+        .filter(|x| x.y > pos.y)
+        .sorted_unstable_by_key(|x| x.y)
+        .peekable();
+
+    let mut row = vec![false; width];
+    let mut next_row = vec![false; width];
+    row[(pos.x - offset) as usize] = true;
+    let mut counter = 1;
+    for y in pos.y + 1..floor {
+        for (i, tile) in next_row.iter_mut().enumerate() {
+            *tile = row[i.saturating_sub(1)..=(i + 1).min(width - 1)]
+                .iter()
+                .any(|&x| x);
+        }
+        while let Some(wall) = walls.next_if(|x| x.y == y) {
+            if let Some(tile) = next_row.get_mut((wall.x - offset) as usize) {
+                *tile = false;
+            }
+        }
+
+        counter += next_row.iter().filter(|&&x| x).count();
+        std::mem::swap(&mut row, &mut next_row);
+    }
+    counter
+}
+
 struct Path {
     path: Vec<Point2>,
 }
 
 impl Path {
-    fn iter(&self) -> PathIter {
+    fn iter(&self) -> PathIter<'_> {
         PathIter {
             path: self,
             target_index: 0,
@@ -186,32 +341,32 @@ fn integer_normalize(mut vector: Vector2) -> Vector2 {
     vector
 }
 
-pub fn day14(content: String) {
-    println!();
-    println!("==== Day 14 ====");
+pub fn day14(content: String) -> anyhow::Result<DayResult> {
+    let mut part1 = String::new();
+    let mut part2 = String::new();
 
-    println!("Part 1");
     let mut map = Map::from_paths(&content, false);
-    println!(
+    writeln!(
+        part1,
         "Fitting grains of sand: {}",
         map.fill_sand(Point2::new(500, 0), 200)
-    );
+    )?;
+
+    let walls = parse_walls(&content)?;
+    writeln!(
+        part2,
+        "Fitting grains of sand: {}",
+        fill_sand_bounded(&walls, Point2::new(500, 0))
+    )?;
 
-    println!();
-    println!("Part 2");
-    let mut _map = Map::from_paths(&content, true);
-    println!("Part 2 skipped for performance");
-    // println!(
-    //     "Fitting grains of sand: {}",
-    //     map.fill_sand(Point2::new(500, 0), 400)
-    // );
+    Ok(DayResult::new(part1, Some(part2)))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    const EXAMPLE: &'static str = r#"498,4 -> 498,6 -> 496,6
+    const EXAMPLE: &str = r#"498,4 -> 498,6 -> 496,6
 503,4 -> 502,4 -> 502,9 -> 494,9"#;
 
     #[test]
@@ -225,4 +380,94 @@ mod tests {
         let mut map = Map::from_paths(EXAMPLE, true);
         assert_eq!(map.fill_sand(Point2::new(500, 0), 100), 93);
     }
+
+    #[test]
+    fn test_fill_matches_single_grains() {
+        for has_floor in [false, true] {
+            let mut map = Map::from_paths(EXAMPLE, has_floor);
+            let mut counter = 0;
+            while map.add_sand(Point2::new(500, 0), 100) == SandOutcome::Settled {
+                counter += 1;
+            }
+
+            let mut cached = Map::from_paths(EXAMPLE, has_floor);
+            assert_eq!(cached.fill_sand(Point2::new(500, 0), 100), counter);
+        }
+    }
+
+    #[test]
+    fn test_sand_outcome() {
+        // Without a floor the grains end up falling into the abyss
+        let mut map = Map::from_paths(EXAMPLE, false);
+        assert_eq!(map.add_sand(Point2::new(500, 0), 100), SandOutcome::Settled);
+        while map.add_sand(Point2::new(500, 0), 100) == SandOutcome::Settled {}
+        assert_eq!(map.add_sand(Point2::new(500, 0), 100), SandOutcome::FellOff);
+        let mut map = Map::from_paths(EXAMPLE, false);
+        let outcome = map.fill_sand_with_outcome(Point2::new(500, 0), 100);
+        assert_eq!(outcome, (24, SandOutcome::FellOff));
+
+        // With a floor they pile up until the source is filled
+        let mut map = Map::from_paths(EXAMPLE, true);
+        while map.add_sand(Point2::new(500, 0), 100) == SandOutcome::Settled {}
+        assert_eq!(map.get(Point2::new(500, 0)), Tile::Sand);
+        assert_eq!(
+            map.add_sand(Point2::new(500, 0), 100),
+            SandOutcome::SourceBlocked
+        );
+        let mut map = Map::from_paths(EXAMPLE, true);
+        let outcome = map.fill_sand_with_outcome(Point2::new(500, 0), 100);
+        assert_eq!(outcome, (93, SandOutcome::SourceBlocked));
+        let outcome = map.fill_sand_with_outcome(Point2::new(500, 0), 100);
+        assert_eq!(outcome, (0, SandOutcome::SourceBlocked));
+    }
+
+    #[test]
+    fn test_dense_grid() {
+        let mut map = Map::from_paths(EXAMPLE, false);
+        // Columns 489 to 511, rows 0 to 10
+        assert_eq!((map.min_x, map.width), (489, 23));
+        assert_eq!(map.tiles.len(), 23 * 11);
+        assert_eq!(map.fill_sand(Point2::new(500, 0), 100), 24);
+
+        let mut map = Map::from_paths(EXAMPLE, true);
+        assert_eq!(map.fill_sand(Point2::new(500, 0), 100), 93);
+        let sand = map.tiles.iter().filter(|&&x| x == Tile::Sand).count();
+        assert_eq!(sand, 93);
+    }
+
+    #[test]
+    fn test_add_sand_traced() {
+        let mut map = Map::from_paths(EXAMPLE, false);
+        let path = map.add_sand_traced(Point2::new(500, 0), 100).unwrap();
+        assert_eq!(path.len(), 9);
+        assert_eq!(path.last(), Some(&Point2::new(500, 8)));
+        assert_eq!(map.get(Point2::new(500, 8)), Tile::Sand);
+
+        while map.add_sand(Point2::new(500, 0), 100) == SandOutcome::Settled {}
+        assert_eq!(map.add_sand_traced(Point2::new(500, 0), 100), None);
+    }
+
+    #[test]
+    fn test_part_2_bounded() {
+        let walls = parse_walls(EXAMPLE).unwrap();
+        assert_eq!(fill_sand_bounded(&walls, Point2::new(500, 0)), 93);
+    }
+
+    #[test]
+    fn test_bounded_tall_map() {
+        let paths = r#"490,20 -> 510,20
+495,40 -> 500,40 -> 500,45
+470,60 -> 480,60 -> 480,55
+505,75 -> 530,75"#;
+        let bounded = fill_sand_bounded(&parse_walls(paths).unwrap(), Point2::new(500, 0));
+        let mut map = Map::from_paths(paths, true);
+        assert_eq!(bounded, map.fill_sand(Point2::new(500, 0), 200));
+    }
+
+    #[test]
+    fn test_day14_output() {
+        let output = day14(EXAMPLE.to_string()).unwrap();
+        assert!(output.part1.contains("Fitting grains of sand: 24"));
+        assert!(output.part2.unwrap().contains("Fitting grains of sand: 93"));
+    }
 }
@@ -1,8 +1,10 @@
-use crate::utils::manhattan_distance;
+use crate::days::DayResult;
+use crate::utils::{manhattan_distance, parse_normalized};
+use anyhow::Context;
 use itertools::Itertools;
-use nom::bytes::complete::tag;
+use nom::bytes::complete::tag_no_case;
 use nom::character::complete;
-use nom::{Finish, IResult};
+use nom::IResult;
 use std::collections::HashSet;
 use std::ops::RangeInclusive;
 use std::str::FromStr;
@@ -10,7 +12,7 @@ use std::str::FromStr;
 type Point2 = na::Point2<i64>;
 
 #[derive(Debug)]
-struct Sensor {
+pub(crate) struct Sensor {
     pos: Point2,
     closest_beacon: Point2,
     #[allow(dead_code)]
@@ -29,6 +31,11 @@ impl Sensor {
     fn distance_to_closest_beacon(&self) -> usize {
         self.distance_to_closest_beacon
     }
+
+    /// Whether `pos` is at least as close as the closest beacon, so no other beacon can be there
+    fn covers(&self, pos: &Point2) -> bool {
+        manhattan_distance(pos, &self.pos) as usize <= self.distance_to_closest_beacon
+    }
 }
 
 #[allow(dead_code)]
@@ -47,17 +54,49 @@ fn count_row_positions_without_beacon(
             continue;
         }
 
-        if sensors
-            .iter()
-            .any(|x| x.distance_to_closest_beacon() >= manhattan_distance(&pos, &x.pos) as usize)
-        {
+        if sensors.iter().any(|x| x.covers(&pos)) {
             count += 1;
         }
     }
     count
 }
 
-fn first_empty_spot(
+/// Merged x-ranges on row `y` that are within reach of at least one sensor
+fn covered_intervals_in_row(sensors: &[Sensor], y: i64) -> Vec<RangeInclusive<i64>> {
+    let mut merged: Vec<RangeInclusive<i64>> = vec![];
+    let intervals = sensors
+        .iter()
+        .filter_map(|sensor| {
+            let reach = sensor.distance_to_closest_beacon() as i64 - (sensor.pos.y - y).abs();
+            (reach >= 0).then(|| sensor.pos.x - reach..=sensor.pos.x + reach)
+        })
+        .sorted_by_key(|x| *x.start());
+    for interval in intervals {
+        match merged.last_mut() {
+            Some(last) if *interval.start() <= *last.end() + 1 => {
+                *last = *last.start()..=*last.end().max(interval.end());
+            }
+            _ => merged.push(interval),
+        }
+    }
+    merged
+}
+
+/// Number of positions on row `y` where no beacon can be
+fn count_covered_in_row(sensors: &[Sensor], y: i64) -> usize {
+    let intervals = covered_intervals_in_row(sensors, y);
+    let covered: i64 = intervals.iter().map(|x| x.end() - x.start() + 1).sum();
+    let beacons = sensors
+        .iter()
+        .map(|x| x.closest_beacon)
+        .filter(|x| x.y == y && intervals.iter().any(|range| range.contains(&x.x)))
+        .unique()
+        .count();
+    covered as usize - beacons
+}
+
+/// Finds the first uncovered position row by row, skipping past each sensor's reach
+pub(crate) fn first_empty_spot(
     sensors: &[Sensor],
     x_range: RangeInclusive<i64>,
     y_range: RangeInclusive<i64>,
@@ -68,20 +107,18 @@ fn first_empty_spot(
         'row: while x <= *x_range.end() {
             let pos = Point2::new(x, y);
 
-            for sensor in sensors {
+            if let Some(sensor) = sensors.iter().find(|x| x.covers(&pos)) {
                 let closest_sensor = sensor.distance_to_closest_beacon();
                 let current_distance = manhattan_distance(&pos, &sensor.pos) as usize;
 
-                if closest_sensor >= current_distance {
-                    if sensor.pos.x > x {
-                        // Mirror around sensor
-                        x += (sensor.pos.x - x) + 1;
-                    } else {
-                        // Move to end of manhattan distance
-                        x += (closest_sensor - current_distance + 1) as i64;
-                    }
-                    continue 'row;
+                if sensor.pos.x > x {
+                    // Mirror around sensor
+                    x += (sensor.pos.x - x) + 1;
+                } else {
+                    // Move to end of manhattan distance
+                    x += (closest_sensor - current_distance + 1) as i64;
                 }
+                continue 'row;
             }
 
             // No sensor in range
@@ -93,14 +130,36 @@ fn first_empty_spot(
     None
 }
 
+/// Finds the only uncovered position within `0..=bound` by checking the tiles just outside each
+/// sensor's reach, as a single gap has to border at least one of them
+pub(crate) fn find_beacon_by_perimeter(sensors: &[Sensor], bound: i64) -> Option<Point2> {
+    let in_bounds = |pos: &Point2| (0..=bound).contains(&pos.x) && (0..=bound).contains(&pos.y);
+    let uncovered = |pos: &Point2| !sensors.iter().any(|x| x.covers(pos));
+
+    sensors.iter().find_map(|sensor| {
+        let radius = sensor.distance_to_closest_beacon() as i64 + 1;
+        (0..radius)
+            .flat_map(|i| {
+                let j = radius - i;
+                [
+                    Point2::new(sensor.pos.x + i, sensor.pos.y + j),
+                    Point2::new(sensor.pos.x + j, sensor.pos.y - i),
+                    Point2::new(sensor.pos.x - i, sensor.pos.y - j),
+                    Point2::new(sensor.pos.x - j, sensor.pos.y + i),
+                ]
+            })
+            .find(|pos| in_bounds(pos) && uncovered(pos))
+    })
+}
+
 fn parse_sensor(input: &str) -> IResult<&str, Sensor> {
-    let (input, _) = tag("Sensor at x=")(input)?;
+    let (input, _) = tag_no_case("Sensor at x=")(input)?;
     let (input, sensor_x) = complete::i64(input)?;
-    let (input, _) = tag(", y=")(input)?;
+    let (input, _) = tag_no_case(", y=")(input)?;
     let (input, sensor_y) = complete::i64(input)?;
-    let (input, _) = tag(": closest beacon is at x=")(input)?;
+    let (input, _) = tag_no_case(": closest beacon is at x=")(input)?;
     let (input, beacon_x) = complete::i64(input)?;
-    let (input, _) = tag(", y=")(input)?;
+    let (input, _) = tag_no_case(", y=")(input)?;
     let (input, beacon_y) = complete::i64(input)?;
 
     Ok((
@@ -113,81 +172,73 @@ fn parse_sensor(input: &str) -> IResult<&str, Sensor> {
 }
 
 impl FromStr for Sensor {
-    type Err = nom::error::Error<String>;
+    type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match parse_sensor(s).finish() {
-            Ok((_remaining, sensor)) => Ok(sensor),
-            Err(nom::error::Error { input, code }) => Err(nom::error::Error {
-                input: input.to_owned(),
-                code,
-            }),
-        }
+        parse_normalized(s, parse_sensor)
     }
 }
 
-pub fn day15(content: String) {
-    println!();
-    println!("==== Day 15 ====");
+pub fn day15(content: String) -> anyhow::Result<DayResult> {
+    solve(&content, 2000000, 4000000)
+}
+
+pub fn part1(content: &str) -> anyhow::Result<String> {
+    covered_in_row(content, 2000000)
+}
+
+pub fn part2(content: &str) -> anyhow::Result<String> {
+    missing_beacon(content, 4000000)
+}
+
+/// Counts the covered positions in row `y` and finds the missing beacon in the square from 0 to
+/// `bound`
+fn solve(content: &str, y: i64, bound: i64) -> anyhow::Result<DayResult> {
+    Ok(DayResult::new(
+        covered_in_row(content, y)?,
+        Some(missing_beacon(content, bound)?),
+    ))
+}
+
+fn parse_sensors(content: &str) -> anyhow::Result<Vec<Sensor>> {
     let sensors = content
         .lines()
-        .map(|x| x.parse::<Sensor>().unwrap())
-        .collect_vec();
+        .map(|x| x.parse::<Sensor>())
+        .collect::<Result<Vec<Sensor>, _>>()?;
+    Ok(sensors)
+}
 
-    println!("Part 1");
-
-    println!("Skipped part 1 for performance");
-
-    // let start = Instant::now();
-    // for r in [10000000] {
-    //     let range = -r..r;
-    //     println!(
-    //         "Positions without beacon in range {:?}: {}",
-    //         range.clone(),
-    //         count_row_positions_without_beacon(&sensors, range, 2000000),
-    //     );
-    // }
-    // println!(
-    //     "Duration: {}",
-    //     Instant::now().duration_since(start).as_secs_f64()
-    // );
-
-    println!();
-    println!("Part 2");
-    let first_empty_spot = first_empty_spot(&sensors, 0..=4000000, 0..=4000000).unwrap();
-    println!(
+fn covered_in_row(content: &str, y: i64) -> anyhow::Result<String> {
+    let sensors = parse_sensors(content)?;
+    Ok(format!(
+        "Positions without beacon: {}",
+        count_covered_in_row(&sensors, y)
+    ))
+}
+
+fn missing_beacon(content: &str, bound: i64) -> anyhow::Result<String> {
+    let sensors = parse_sensors(content)?;
+    let missing_beacon =
+        find_beacon_by_perimeter(&sensors, bound).context("no position left for the beacon")?;
+    Ok(format!(
         "Missing beacon: {}",
-        calc_tuning_frequency(first_empty_spot)
-    );
+        calc_tuning_frequency(missing_beacon)
+    ))
 }
 
-fn calc_tuning_frequency(pos: Point2) -> i64 {
+pub(crate) fn calc_tuning_frequency(pos: Point2) -> i64 {
     pos.x * 4000000 + pos.y
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::load_example;
     use itertools::Itertools;
 
-    const EXAMPLE: &'static str = r#"Sensor at x=2, y=18: closest beacon is at x=-2, y=15
-Sensor at x=9, y=16: closest beacon is at x=10, y=16
-Sensor at x=13, y=2: closest beacon is at x=15, y=3
-Sensor at x=12, y=14: closest beacon is at x=10, y=16
-Sensor at x=10, y=20: closest beacon is at x=10, y=16
-Sensor at x=14, y=17: closest beacon is at x=10, y=16
-Sensor at x=8, y=7: closest beacon is at x=2, y=10
-Sensor at x=2, y=0: closest beacon is at x=2, y=10
-Sensor at x=0, y=11: closest beacon is at x=2, y=10
-Sensor at x=20, y=14: closest beacon is at x=25, y=17
-Sensor at x=17, y=20: closest beacon is at x=21, y=22
-Sensor at x=16, y=7: closest beacon is at x=15, y=3
-Sensor at x=14, y=3: closest beacon is at x=15, y=3
-Sensor at x=20, y=1: closest beacon is at x=15, y=3"#;
-
     #[test]
     fn test_part_1() {
-        let sensors = EXAMPLE
+        let sensors = load_example(15)
             .lines()
             .map(|x| x.parse::<Sensor>().unwrap())
             .collect_vec();
@@ -198,9 +249,71 @@ Sensor at x=20, y=1: closest beacon is at x=15, y=3"#;
         );
     }
 
+    #[test]
+    fn test_parse_odd_spacing() {
+        let sensor = "  Sensor at x=2,  y=18:  closest beacon is at x=-2, y=15 "
+            .parse::<Sensor>()
+            .unwrap();
+        assert_eq!(sensor.pos, Point2::new(2, 18));
+        assert_eq!(sensor.closest_beacon, Point2::new(-2, 15));
+        assert!("sensor at X=2, Y=18: Closest beacon is at x=-2, y=15"
+            .parse::<Sensor>()
+            .is_ok());
+
+        let err = "Sensor at x=2, y=18: closest beacon is at x=-2, z=15"
+            .parse::<Sensor>()
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "could not parse 'Sensor at x=2, y=18: closest beacon is at x=-2, z=15' at ', z=15'"
+        );
+    }
+
+    #[test]
+    fn test_covered_intervals() {
+        let sensors = load_example(15)
+            .lines()
+            .map(|x| x.parse::<Sensor>().unwrap())
+            .collect_vec();
+
+        assert_eq!(covered_intervals_in_row(&sensors, 10), vec![-2..=24]);
+        assert_eq!(count_covered_in_row(&sensors, 10), 26);
+        assert_eq!(
+            count_covered_in_row(&sensors, 10),
+            count_row_positions_without_beacon(&sensors, -10..30, 10)
+        );
+    }
+
+    #[test]
+    fn test_covered_large_row() {
+        let sensors = [
+            "Sensor at x=0, y=2000000: closest beacon is at x=1000000, y=2000000",
+            "Sensor at x=3000000, y=1000000: closest beacon is at x=3000000, y=2500000",
+        ]
+        .iter()
+        .map(|x| x.parse::<Sensor>().unwrap())
+        .collect_vec();
+
+        assert_eq!(
+            covered_intervals_in_row(&sensors, 2000000),
+            vec![-1000000..=1000000, 2500000..=3500000]
+        );
+        assert_eq!(count_covered_in_row(&sensors, 2000000), 2000000 + 1000001);
+    }
+
+    #[test]
+    fn test_covers() {
+        let sensor = Sensor::new(Point2::new(8, 7), Point2::new(2, 10));
+        assert!(sensor.covers(&Point2::new(8, 16)));
+        assert!(sensor.covers(&Point2::new(2, 10)));
+        assert!(!sensor.covers(&Point2::new(8, 17)));
+        assert!(!sensor.covers(&Point2::new(-2, 7)));
+    }
+
     #[test]
     fn test_part_2() {
-        let sensors = EXAMPLE
+        let sensors = load_example(15)
             .lines()
             .map(|x| x.parse::<Sensor>().unwrap())
             .collect_vec();
@@ -209,4 +322,24 @@ Sensor at x=20, y=1: closest beacon is at x=15, y=3"#;
         assert_eq!(pos, Some(Point2::new(14, 11)));
         assert_eq!(calc_tuning_frequency(pos.unwrap()), 56000011);
     }
+
+    #[test]
+    fn test_find_beacon_by_perimeter() {
+        let sensors = load_example(15)
+            .lines()
+            .map(|x| x.parse::<Sensor>().unwrap())
+            .collect_vec();
+
+        let pos = find_beacon_by_perimeter(&sensors, 20);
+        assert_eq!(pos, Some(Point2::new(14, 11)));
+        assert_eq!(pos, first_empty_spot(&sensors, 0..=20, 0..=20));
+        assert_eq!(calc_tuning_frequency(pos.unwrap()), 56000011);
+    }
+
+    #[test]
+    fn test_solve_output() {
+        let output = solve(&load_example(15), 10, 20).unwrap();
+        assert!(output.part1.contains("Positions without beacon: 26"));
+        assert_eq!(output.part2.as_deref(), Some("Missing beacon: 56000011"));
+    }
 }
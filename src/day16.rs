@@ -1,10 +1,19 @@
+use crate::days::DayResult;
+use crate::utils::parse_normalized;
+use anyhow::{bail, Context};
+use derivative::Derivative;
 use itertools::Itertools;
 use nom::branch::alt;
-use nom::bytes::complete::{tag, take};
+use nom::bytes::complete::{tag, tag_no_case, take};
 use nom::character::complete;
 use nom::multi::separated_list1;
-use nom::{Finish, IResult};
+use nom::sequence::tuple;
+use nom::IResult;
 use pathfinding::prelude::dijkstra;
+#[cfg(feature = "day16-bitmask")]
+use std::collections::hash_map::Entry;
+#[cfg(feature = "day16-bitmask")]
+use std::collections::VecDeque;
 use std::collections::{BTreeSet, HashMap};
 use std::str::FromStr;
 
@@ -195,9 +204,14 @@ impl SearchNode {
     }
 }
 
-#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Default, Derivative)]
+#[derivative(Eq, PartialEq, Hash)]
 struct PressureTracker {
     open_valves: BTreeSet<usize>,
+    /// Valves in the order they were opened. Opening the same valves in a different order still
+    /// leads to the same search state, so this is only reliable when replaying a single path.
+    #[derivative(PartialEq = "ignore", Hash = "ignore")]
+    open_order: Vec<usize>,
 }
 
 impl PressureTracker {
@@ -206,15 +220,41 @@ impl PressureTracker {
     }
 
     pub fn open_valve(&mut self, node: usize, graph: &Graph) -> bool {
-        graph.flow_rate(node) > 0 && self.open_valves.insert(node)
+        if graph.flow_rate(node) > 0 && self.open_valves.insert(node) {
+            self.open_order.push(node);
+            true
+        } else {
+            false
+        }
     }
+
     pub fn pressure_released(&self, graph: &Graph) -> u64 {
         self.open_valves.iter().map(|x| graph.flow_rate(*x)).sum()
     }
+
+    /// Names and flow rates of all currently opened valves
+    #[allow(dead_code)]
+    pub fn opened(&self, graph: &Graph) -> Vec<(String, u64)> {
+        self.open_valves
+            .iter()
+            .map(|&x| {
+                let valve = graph.nodes.get(x).unwrap();
+                (valve.name.clone(), valve.flow_rate)
+            })
+            .collect()
+    }
+
+    /// Names of the opened valves, in the order they were opened
+    pub fn open_sequence<'a>(&self, graph: &'a Graph) -> Vec<&'a str> {
+        self.open_order
+            .iter()
+            .map(|&x| graph.nodes[x].name.as_str())
+            .collect()
+    }
 }
 
 #[derive(Debug, Default)]
-struct Graph {
+pub(crate) struct Graph {
     start: usize,
     nodes: Vec<Valve>,
     edges: HashMap<usize, Vec<usize>>,
@@ -240,11 +280,34 @@ impl Graph {
         self.nodes.get(node).unwrap().flow_rate
     }
 
+    /// Checks that every tunnel can be walked in both directions
+    pub fn assert_undirected(&self) -> anyhow::Result<()> {
+        for from in 0..self.nodes.len() {
+            for &to in self.edges.get(&from).into_iter().flatten() {
+                if !self.edges.get(&to).is_some_and(|x| x.contains(&from)) {
+                    bail!(
+                        "tunnel from {} to {} has no way back",
+                        self.nodes[from].name,
+                        self.nodes[to].name
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn neighbors(&self, from: usize) -> &[usize] {
         self.edges.get(&from).unwrap()
     }
 
+    #[allow(dead_code)]
     pub fn optimal_pressure_release(&self, max_time: u64) -> u64 {
+        self.optimal_path(max_time).0
+    }
+
+    /// Like `optimal_pressure_release`, but also returns the state of every minute on the way
+    #[allow(dead_code)]
+    fn optimal_path(&self, max_time: u64) -> (u64, Vec<SearchNode>) {
         let (path, cost) = dijkstra(
             &SearchNode::new(self.start),
             |x| x.successors(self),
@@ -256,9 +319,9 @@ impl Graph {
 
         // Revert cost to get released pressure
         let score = (max_time * self.all_valves_open) - cost;
-        let recalc_score = path.iter().rev().skip(1).map(|x| x.score(self)).sum();
+        let recalc_score: u64 = path.iter().rev().skip(1).map(|x| x.score(self)).sum();
         assert_eq!(score, recalc_score);
-        score
+        (score, path)
     }
 
     #[allow(dead_code)]
@@ -274,32 +337,151 @@ impl Graph {
 
         // Revert cost to get released pressure
         let score = (max_time * self.all_valves_open) - cost;
-        let recalc_score = path.iter().rev().skip(1).map(|x| x.score(self)).sum();
+        let recalc_score: u64 = path.iter().rev().skip(1).map(|x| x.score(self)).sum();
         assert_eq!(score, recalc_score);
         score
     }
+
+    /// Like `optimal_pressure_release`, but only decides which valve to open next and spends the
+    /// time to walk there at once
+    #[cfg(feature = "day16-bitmask")]
+    pub fn optimal_pressure_release_fast(&self, max_time: u64) -> u64 {
+        self.best_per_subset(max_time)
+            .into_values()
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Number of steps between every pair of connected valves
+    #[cfg(feature = "day16-bitmask")]
+    pub fn shortest_paths(&self) -> HashMap<(usize, usize), u64> {
+        let mut distances = HashMap::new();
+        for start in 0..self.nodes.len() {
+            let mut queue = VecDeque::from([(start, 0)]);
+            distances.insert((start, start), 0);
+            while let Some((node, distance)) = queue.pop_front() {
+                for &next in self.edges.get(&node).into_iter().flatten() {
+                    if let Entry::Vacant(entry) = distances.entry((start, next)) {
+                        entry.insert(distance + 1);
+                        queue.push_back((next, distance + 1));
+                    }
+                }
+            }
+        }
+        distances
+    }
+
+    /// Best release when splitting the valves between me and the elephant, so that each of us
+    /// opens a disjoint set of valves on our own
+    #[cfg(feature = "day16-bitmask")]
+    pub fn duo_optimal_pressure_release_fast(&self, max_time: u64) -> u64 {
+        let best = self.best_per_subset(max_time);
+        // One of us can also open every valve alone
+        let alone = best.values().copied().max().unwrap_or(0);
+        best.iter()
+            .tuple_combinations()
+            .filter(|((mine, _), (elephants, _))| *mine & *elephants == 0)
+            .map(|((_, mine), (_, elephants))| mine + elephants)
+            .fold(alone, u64::max)
+    }
+
+    /// Best release for every set of opened valves reachable in time by a single agent. Valves
+    /// with a flow rate are numbered in order of their ids, bit `i` being set if the `i`-th one
+    /// is opened.
+    #[cfg(feature = "day16-bitmask")]
+    pub fn best_per_subset(&self, max_time: u64) -> HashMap<u64, u64> {
+        let distances = self.shortest_paths();
+        let valves = (0..self.nodes.len())
+            .filter(|&x| self.flow_rate(x) > 0)
+            .collect_vec();
+        assert!(valves.len() <= 64, "too many valves for a bitmask");
+
+        let mut best = HashMap::new();
+        // Best release seen per (valve, time left, opened valves), reaching the same state with
+        // less released can't do better from there
+        let mut seen = HashMap::new();
+        let mut stack = vec![(self.start, max_time, 0u64, 0)];
+        while let Some((node, time_left, opened, released)) = stack.pop() {
+            if seen
+                .get(&(node, time_left, opened))
+                .is_some_and(|&x| x >= released)
+            {
+                continue;
+            }
+            seen.insert((node, time_left, opened), released);
+
+            let entry = best.entry(opened).or_insert(0);
+            *entry = released.max(*entry);
+
+            for (bit, &valve) in valves.iter().enumerate() {
+                if opened & (1 << bit) != 0 {
+                    continue;
+                }
+
+                // Walk to the valve and open it
+                let Some(distance) = distances.get(&(node, valve)) else {
+                    continue;
+                };
+                let cost = distance + 1;
+                if cost >= time_left {
+                    continue;
+                }
+                let time_left = time_left - cost;
+                stack.push((
+                    valve,
+                    time_left,
+                    opened | (1 << bit),
+                    released + time_left * self.flow_rate(valve),
+                ));
+            }
+        }
+        best
+    }
 }
 
+/// Whether the step from `from` to `to` opens the valve at the current position
+fn is_opening(from: &SearchNode, to: &SearchNode) -> bool {
+    from.node == to.node && from.pressure != to.pressure
+}
+
+/// Opens the valves in the same order as along `path`
 #[allow(dead_code)]
-fn print_path(path: &[SearchNode], graph: &Graph) {
-    for window in path.windows(2) {
-        let (from, to): (&SearchNode, &SearchNode) = window.iter().collect_tuple().unwrap();
+fn replay_openings(path: &[SearchNode], graph: &Graph) -> PressureTracker {
+    let mut pressure = PressureTracker::default();
+    for (from, to) in path.iter().tuple_windows() {
+        if is_opening(from, to) {
+            pressure.open_valve(from.node, graph);
+        }
+    }
+    pressure
+}
 
+#[allow(dead_code)]
+fn print_path(path: &[SearchNode], graph: &Graph) {
+    // The search states don't reliably know the order, so it's replayed along the path
+    let mut pressure = PressureTracker::default();
+    for (from, to) in path.iter().tuple_windows() {
         println!("== Minute {} ==", from.time + 1);
-        if from.pressure.open_valves.is_empty() {
-            println!("No valves are open.");
-        } else {
-            println!(
-                "Valve ?? is open, releasing {} pressure.",
-                from.pressure.pressure_released(graph)
-            );
+        match pressure.open_sequence(graph).as_slice() {
+            [] => println!("No valves are open."),
+            [valve] => println!(
+                "Valve {} is open, releasing {} pressure.",
+                valve,
+                pressure.pressure_released(graph)
+            ),
+            valves => println!(
+                "Valves {} are open, releasing {} pressure.",
+                valves.join(", "),
+                pressure.pressure_released(graph)
+            ),
         }
 
         let from_name = &graph.nodes.get(from.node).unwrap().name;
         let to_name = &graph.nodes.get(to.node).unwrap().name;
-        if from.node == to.node {
+        if is_opening(from, to) {
+            pressure.open_valve(from.node, graph);
             println!("You open valve {}", from_name);
-        } else {
+        } else if from.node != to.node {
             println!("You move to valve {}", to_name);
         }
         println!();
@@ -316,7 +498,12 @@ fn print_duo_path(path: &[DuoSearchNode], graph: &Graph) {
             println!("No valves are open.");
         } else {
             println!(
-                "Valve ?? is open, releasing {} pressure.",
+                "Valves {} are open, releasing {} pressure.",
+                from.pressure
+                    .opened(graph)
+                    .iter()
+                    .map(|(name, _)| name)
+                    .join(", "),
                 from.pressure.pressure_released(graph)
             );
         }
@@ -340,7 +527,7 @@ fn print_duo_path(path: &[DuoSearchNode], graph: &Graph) {
 }
 
 #[derive(Debug)]
-struct Valve {
+pub(crate) struct Valve {
     name: String,
     flow_rate: u64,
 }
@@ -351,28 +538,28 @@ impl Valve {
     }
 }
 
-fn parse_graph(graph: &str) -> Graph {
-    let definitions = graph
-        .lines()
-        .map(|x| x.parse::<ValveDefinition>().unwrap())
-        .collect_vec();
+pub(crate) fn parse_graph(graph: &str) -> anyhow::Result<Graph> {
+    let definitions: Vec<ValveDefinition> = graph.lines().map(|x| x.parse()).try_collect()?;
 
     let mut graph = Graph::new();
 
-    let name_to_id: HashMap<_, _> = definitions
-        .iter()
-        .map(|x| {
-            (
-                x.name.clone(),
-                graph.add_node(Valve::new(x.name.clone(), x.flow_rate)),
-            )
-        })
-        .collect();
-
-    for definition in definitions {
-        let from_id = *name_to_id.get(&definition.name).unwrap();
+    let mut name_to_id = HashMap::new();
+    for definition in &definitions {
+        let id = graph.add_node(Valve::new(definition.name.clone(), definition.flow_rate));
+        if name_to_id.insert(definition.name.as_str(), id).is_some() {
+            bail!("valve {} is defined twice", definition.name);
+        }
+    }
+
+    // Valves are numbered in the order they are defined
+    for (from_id, definition) in definitions.iter().enumerate() {
         for neighbor in &definition.neighbors {
-            let to_id = *name_to_id.get(neighbor).unwrap();
+            let to_id = *name_to_id.get(neighbor.as_str()).with_context(|| {
+                format!(
+                    "valve {} leads to unknown valve {}",
+                    definition.name, neighbor
+                )
+            })?;
 
             graph.add_edge(from_id, to_id);
         }
@@ -380,9 +567,11 @@ fn parse_graph(graph: &str) -> Graph {
         graph.all_valves_open += definition.flow_rate;
     }
 
-    graph.start = *name_to_id.get("AA").unwrap();
+    graph.start = *name_to_id
+        .get("AA")
+        .context("there is no valve AA to start at")?;
 
-    graph
+    Ok(graph)
 }
 
 struct ValveDefinition {
@@ -392,14 +581,17 @@ struct ValveDefinition {
 }
 
 fn parse_valve_definition(input: &str) -> IResult<&str, ValveDefinition> {
-    let (input, _) = tag("Valve ")(input)?;
+    let (input, _) = tag_no_case("Valve ")(input)?;
     let (input, name) = take(2usize)(input)?;
-    let (input, _) = tag(" has flow rate=")(input)?;
+    let (input, _) = tag_no_case(" has flow rate=")(input)?;
     let (input, flow_rate) = complete::u64(input)?;
     let (input, _) = tag("; ")(input)?;
-    let (input, _) = alt((
-        tag("tunnel leads to valve "),
-        tag("tunnels lead to valves "),
+    // Singular and plural are accepted in any mix, hand written inputs rarely get them right
+    let (input, _) = tuple((
+        alt((tag_no_case("tunnels "), tag_no_case("tunnel "))),
+        alt((tag_no_case("leads "), tag_no_case("lead "))),
+        tag_no_case("to "),
+        alt((tag_no_case("valves "), tag_no_case("valve "))),
     ))(input)?;
     let (input, neighbors) = separated_list1(tag(", "), take(2usize))(input)?;
     Ok((
@@ -413,64 +605,203 @@ fn parse_valve_definition(input: &str) -> IResult<&str, ValveDefinition> {
 }
 
 impl FromStr for ValveDefinition {
-    type Err = nom::error::Error<String>;
+    type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match parse_valve_definition(s).finish() {
-            Ok((_remaining, definitions)) => Ok(definitions),
-            Err(nom::error::Error { input, code }) => Err(nom::error::Error {
-                input: input.to_owned(),
-                code,
-            }),
-        }
+        parse_normalized(s, parse_valve_definition)
     }
 }
 
-pub fn day16(content: String) {
-    println!();
-    println!("==== Day 16 ====");
-    let graph = parse_graph(&content);
-
-    println!("Part 1");
-    println!(
-        "Optimal pressure release: {}",
-        graph.optimal_pressure_release(30)
-    );
-
-    println!();
-    println!("Part 2");
-    println!("Skipping Part 2");
-    // println!(
-    //     "Optimal duo pressure release: {}",
-    //     graph.duo_optimal_pressure_release(26)
-    // );
+pub fn day16(content: String) -> anyhow::Result<DayResult> {
+    Ok(DayResult::new(part1(&content)?, Some(part2(&content)?)))
+}
+
+/// With the `day16-bitmask` feature, both parts are solved over subsets of opened valves, otherwise
+/// with the much slower search over every minute
+pub fn part1(content: &str) -> anyhow::Result<String> {
+    let graph = parse_graph(content)?;
+    // Hand edited inputs are only checked in debug builds
+    if cfg!(debug_assertions) {
+        graph.assert_undirected()?;
+    }
+    #[cfg(feature = "day16-bitmask")]
+    let release = graph.optimal_pressure_release_fast(30);
+    #[cfg(not(feature = "day16-bitmask"))]
+    let release = graph.optimal_pressure_release(30);
+    Ok(format!("Optimal pressure release: {}", release))
+}
+
+pub fn part2(content: &str) -> anyhow::Result<String> {
+    let graph = parse_graph(content)?;
+    // Hand edited inputs are only checked in debug builds
+    if cfg!(debug_assertions) {
+        graph.assert_undirected()?;
+    }
+    #[cfg(feature = "day16-bitmask")]
+    let release = graph.duo_optimal_pressure_release_fast(26);
+    #[cfg(not(feature = "day16-bitmask"))]
+    let release = graph.duo_optimal_pressure_release(26);
+    Ok(format!("Optimal duo pressure release: {}", release))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-
-    const EXAMPLE: &'static str = r#"Valve AA has flow rate=0; tunnels lead to valves DD, II, BB
-Valve BB has flow rate=13; tunnels lead to valves CC, AA
-Valve CC has flow rate=2; tunnels lead to valves DD, BB
-Valve DD has flow rate=20; tunnels lead to valves CC, AA, EE
-Valve EE has flow rate=3; tunnels lead to valves FF, DD
-Valve FF has flow rate=0; tunnels lead to valves EE, GG
-Valve GG has flow rate=0; tunnels lead to valves FF, HH
-Valve HH has flow rate=22; tunnel leads to valve GG
-Valve II has flow rate=0; tunnels lead to valves AA, JJ
-Valve JJ has flow rate=21; tunnel leads to valve II"#;
+    use crate::utils::load_example;
 
     #[test]
     fn test_part_1() {
-        let graph = parse_graph(EXAMPLE);
+        let graph = parse_graph(&load_example(16)).unwrap();
         assert_eq!(graph.optimal_pressure_release(30), 1651);
     }
 
+    #[test]
+    fn test_opening_order() {
+        let graph = parse_graph(&load_example(16)).unwrap();
+        let (score, path) = graph.optimal_path(30);
+        assert_eq!(score, 1651);
+
+        let opened = replay_openings(&path, &graph);
+        assert_eq!(
+            opened.open_sequence(&graph),
+            ["DD", "BB", "JJ", "HH", "EE", "CC"]
+        );
+    }
+
+    #[test]
+    fn test_parse_odd_spacing() {
+        let valve = "Valve AA  has flow rate=0;  tunnels lead to valve DD, II"
+            .parse::<ValveDefinition>()
+            .unwrap();
+        assert_eq!(valve.name, "AA");
+        assert_eq!(valve.flow_rate, 0);
+        assert_eq!(valve.neighbors, vec!["DD", "II"]);
+
+        let valve = " Valve HH has flow rate=22; tunnel leads to valves GG "
+            .parse::<ValveDefinition>()
+            .unwrap();
+        assert_eq!(valve.neighbors, vec!["GG"]);
+
+        let err = "Valve AA has flow rate=0; tunnels go to valves DD"
+            .parse::<ValveDefinition>()
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "could not parse 'Valve AA has flow rate=0; tunnels go to valves DD' at 'go to valves DD'"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "day16-bitmask")]
+    fn test_part_1_fast() {
+        let graph = parse_graph(&load_example(16)).unwrap();
+        assert_eq!(graph.optimal_pressure_release_fast(30), 1651);
+
+        let distances = graph.shortest_paths();
+        // AA to HH via DD, EE, FF and GG
+        assert_eq!(distances.get(&(0, 7)), Some(&5));
+        assert_eq!(distances.get(&(7, 0)), Some(&5));
+    }
+
+    #[test]
+    fn test_parse_graph_errors() {
+        let example = load_example(16);
+        let bad_line = example.replace("Valve CC has", "Valve CC hss");
+        let err = parse_graph(&bad_line).unwrap_err();
+        assert!(err.to_string().contains("at ' hss flow rate=2"), "{}", err);
+
+        let unknown = example.replace("valves DD, II, BB", "valves DD, II, XX");
+        let err = parse_graph(&unknown).unwrap_err();
+        assert_eq!(err.to_string(), "valve AA leads to unknown valve XX");
+
+        let no_start = example.replace("AA", "AB");
+        let err = parse_graph(&no_start).unwrap_err();
+        assert_eq!(err.to_string(), "there is no valve AA to start at");
+
+        let twice = format!(
+            "{}\nValve BB has flow rate=1; tunnel leads to valve AA",
+            example
+        );
+        let err = parse_graph(&twice).unwrap_err();
+        assert_eq!(err.to_string(), "valve BB is defined twice");
+    }
+
+    #[test]
+    fn test_assert_undirected() {
+        assert!(parse_graph(&load_example(16))
+            .unwrap()
+            .assert_undirected()
+            .is_ok());
+
+        let one_way = load_example(16).replace(
+            "Valve II has flow rate=0; tunnels lead to valves AA, JJ",
+            "Valve II has flow rate=0; tunnel leads to valve AA",
+        );
+        let err = parse_graph(&one_way)
+            .unwrap()
+            .assert_undirected()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "tunnel from JJ to II has no way back");
+    }
+
+    #[test]
+    fn test_opened_valves() {
+        let graph = parse_graph(&load_example(16)).unwrap();
+        let mut pressure = PressureTracker::default();
+        for name in ["DD", "BB", "JJ", "AA"] {
+            let (id, _) = graph
+                .nodes
+                .iter()
+                .find_position(|x| x.name == name)
+                .unwrap();
+            pressure.open_valve(id, &graph);
+        }
+
+        let opened = pressure.opened(&graph);
+        // AA has no flow and can't be opened
+        assert_eq!(opened.len(), 3);
+        assert!(opened.contains(&("JJ".to_owned(), 21)));
+        let total: u64 = opened.iter().map(|(_, flow_rate)| flow_rate).sum();
+        assert_eq!(total, pressure.pressure_released(&graph));
+    }
+
     #[test]
     fn test_part_2() {
-        let graph = parse_graph(EXAMPLE);
+        let graph = parse_graph(&load_example(16)).unwrap();
         println!("graph {:#?}", graph);
         assert_eq!(graph.duo_optimal_pressure_release(26), 1707);
     }
+
+    #[test]
+    #[cfg(feature = "day16-bitmask")]
+    fn test_part_2_fast() {
+        let graph = parse_graph(&load_example(16)).unwrap();
+        assert_eq!(graph.duo_optimal_pressure_release_fast(26), 1707);
+    }
+
+    #[test]
+    #[cfg(feature = "day16-bitmask")]
+    fn test_best_per_subset() {
+        let graph = parse_graph(&load_example(16)).unwrap();
+        let best = graph.best_per_subset(30);
+        assert_eq!(best.values().max(), Some(&1651));
+        // Nothing opened
+        assert_eq!(best.get(&0), Some(&0));
+
+        let best = graph.best_per_subset(26);
+        let duo = best
+            .iter()
+            .tuple_combinations()
+            .filter(|((mine, _), (elephants, _))| *mine & *elephants == 0)
+            .map(|((_, mine), (_, elephants))| mine + elephants)
+            .max();
+        assert_eq!(duo, Some(1707));
+    }
+
+    #[test]
+    fn test_day16_output() {
+        let output = day16(load_example(16)).unwrap();
+        assert!(output.part1.contains("Optimal pressure release: 1651"));
+    }
 }
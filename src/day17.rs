@@ -1,6 +1,7 @@
-use anyhow::bail;
+use crate::days::DayResult;
+use anyhow::{bail, Context};
 use itertools::Itertools;
-use std::iter;
+use std::fmt::Write;
 use std::str::FromStr;
 
 const ROCKS: &str = r#"####
@@ -25,7 +26,7 @@ type Point2 = na::Point2<i64>;
 type Vector2 = na::Vector2<i64>;
 
 #[derive(Debug)]
-struct Rock {
+pub(crate) struct Rock {
     /// Tiles occupied by the rock, as offsets from the rock origin point (bottom left)
     positions: Vec<Vector2>,
     #[allow(dead_code)]
@@ -63,9 +64,12 @@ impl FromStr for Rock {
 }
 
 struct Map {
+    /// Rows from `floor` up to `current_height`
     fallen_rocks: Vec<bool>,
     width: usize,
     current_height: usize,
+    /// Rows below were pruned and count as occupied
+    floor: usize,
 }
 
 impl Map {
@@ -74,6 +78,7 @@ impl Map {
             fallen_rocks: vec![],
             width,
             current_height: 0,
+            floor: 0,
         }
     }
 
@@ -92,7 +97,7 @@ impl Map {
             let missing_rows = (pos.y - self.current_height as i64 + 1).max(0) as usize;
             if missing_rows > 0 {
                 self.fallen_rocks
-                    .extend(iter::repeat(false).take(missing_rows * self.width));
+                    .resize(self.fallen_rocks.len() + missing_rows * self.width, false);
                 self.current_height += missing_rows;
             }
         }
@@ -122,7 +127,7 @@ impl Map {
     }
 
     fn index(&self, pos: &Point2) -> Index {
-        if pos.x < 0 || pos.x >= self.width as i64 || pos.y < 0 {
+        if pos.x < 0 || pos.x >= self.width as i64 || pos.y < self.floor as i64 {
             return Index::Outside;
         }
 
@@ -130,22 +135,60 @@ impl Map {
             return Index::Above;
         }
 
-        Index::Inside((pos.x + pos.y * self.width as i64) as usize)
+        let y = pos.y - self.floor as i64;
+        Index::Inside((pos.x + y * self.width as i64) as usize)
     }
 
     fn top_rows(&self, lower: usize, upper: usize) -> &[bool] {
-        let lower = lower.min(self.current_height);
-        let upper = upper.min(self.current_height);
+        let lower = lower.clamp(self.floor, self.current_height) - self.floor;
+        let upper = upper.clamp(self.floor, self.current_height) - self.floor;
 
         let lower = lower * self.width;
         let upper = upper * self.width;
         self.fallen_rocks.get(lower..upper).unwrap()
     }
 
+    /// Lowest row a falling rock can still reach. Rocks only move sideways and down, so every
+    /// part of them has to pass through free positions connected to the top in that way.
+    #[allow(dead_code)]
+    fn reachable_floor(&self) -> usize {
+        let mut reached = vec![false; self.fallen_rocks.len()];
+        let top = self.current_height as i64 - 1;
+        let mut stack = (0..self.width as i64)
+            .map(|x| Point2::new(x, top))
+            .collect_vec();
+
+        let mut floor = self.current_height;
+        while let Some(pos) = stack.pop() {
+            let Index::Inside(index) = self.index(&pos) else {
+                continue;
+            };
+            if self.fallen_rocks[index] || reached[index] {
+                continue;
+            }
+
+            reached[index] = true;
+            floor = floor.min(pos.y as usize);
+            stack.extend(
+                [Vector2::new(-1, 0), Vector2::new(1, 0), Vector2::new(0, -1)].map(|x| pos + x),
+            );
+        }
+        floor
+    }
+
+    /// Drops all rows below the lowest one a rock can still reach, they can only ever block
+    /// rocks from falling further
+    #[allow(dead_code)]
+    fn prune_below_floor(&mut self) {
+        let floor = self.reachable_floor();
+        self.fallen_rocks.drain(..(floor - self.floor) * self.width);
+        self.floor = floor;
+    }
+
     #[allow(dead_code)]
     fn to_pretty_string(&self) -> String {
         let mut map = String::new();
-        for y in (0..self.current_height).rev() {
+        for y in (self.floor..self.current_height).rev() {
             map.push('|');
             for x in 0..self.width {
                 if self.get(&Point2::new(x as i64, y as i64)) {
@@ -174,8 +217,8 @@ enum Index {
     Inside(usize),
 }
 
-#[derive(Copy, Clone)]
-enum Movement {
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum Movement {
     Left,
     Right,
 }
@@ -212,7 +255,7 @@ struct FallingRockPattern {
     current_rocks: usize,
 }
 
-struct FallingRocks<'a> {
+pub(crate) struct FallingRocks<'a> {
     map: Map,
     movements: &'a [Movement],
     next_movement: usize,
@@ -225,7 +268,7 @@ struct FallingRocks<'a> {
 }
 
 impl<'a> FallingRocks<'a> {
-    fn new(width: usize, rocks: &'a [Rock], movements: &'a [Movement]) -> Self {
+    pub(crate) fn new(width: usize, rocks: &'a [Rock], movements: &'a [Movement]) -> Self {
         Self {
             map: Map::new(width),
             movements,
@@ -253,49 +296,76 @@ impl<'a> FallingRocks<'a> {
         self.map.top_rows(lower, upper)
     }
 
-    fn current_height(&self) -> usize {
+    pub(crate) fn current_height(&self) -> usize {
         self.map.current_height + self.additional_height
     }
 
-    fn drop_n_rocks(&mut self, n: usize) {
+    pub(crate) fn drop_n_rocks(&mut self, n: usize) {
         for _ in 0..n {
             self.drop_next_rock();
         }
     }
 
-    fn drop_n_rocks_with_period_search(&mut self, n: usize, warmup: usize) {
-        assert!(n > warmup);
-
-        self.drop_n_rocks(warmup);
-
-        let max_fall_height = self.max_fall_height;
+    /// Drops another `n` rocks and returns the resulting height
+    #[allow(dead_code)]
+    fn height_after(&mut self, n: usize) -> usize {
+        self.drop_n_rocks(n);
+        self.current_height()
+    }
 
-        let search_pattern = self.current_pattern(warmup);
-        let mut i = warmup;
+    /// Drops `n` rocks, skipping ahead once the top of the tower starts repeating.
+    ///
+    /// The compared area depends on the highest fall seen so far, so the search only starts once
+    /// that stopped growing for a full cycle through all rocks and movements, and starts over if
+    /// it grows again later.
+    ///
+    /// Returns the number of rocks and the height gained per period, if one was found.
+    fn drop_n_rocks_with_period_search(&mut self, n: usize) -> Option<(usize, usize)> {
+        let stable_after = self.rocks.len() * self.movements.len();
+
+        let mut max_fall_height = self.max_fall_height;
+        let mut last_increase = 0;
+        let mut search_pattern: Option<FallingRockPattern> = None;
+        let mut period = None;
+        let mut i = 0;
         while i < n {
             self.drop_next_rock();
             i += 1;
 
-            assert_eq!(max_fall_height, self.max_fall_height);
+            if self.max_fall_height != max_fall_height {
+                max_fall_height = self.max_fall_height;
+                last_increase = i;
+                search_pattern = None;
+                continue;
+            }
 
-            if self.next_rock == search_pattern.next_rock
-                && self.next_movement == search_pattern.next_movement
-                && self.current_area() == search_pattern.area
+            let Some(pattern) = &search_pattern else {
+                if i - last_increase >= stable_after {
+                    search_pattern = Some(self.current_pattern(i));
+                }
+                continue;
+            };
+
+            if self.next_rock == pattern.next_rock
+                && self.next_movement == pattern.next_movement
+                && self.current_area() == pattern.area
             {
                 // Pattern is fully aligned, skip to the end now!
-                let rock_diff = i - search_pattern.current_rocks;
-                let height_diff = self.current_height() - search_pattern.current_height;
+                let rock_diff = i - pattern.current_rocks;
+                let height_diff = self.current_height() - pattern.current_height;
 
-                let missing_rocks = n - i - 1;
+                let missing_rocks = n - i;
                 let loops = missing_rocks / rock_diff;
 
                 i += loops * rock_diff;
                 self.additional_height += loops * height_diff;
+                period = Some((rock_diff, height_diff));
                 break;
             }
         }
 
         self.drop_n_rocks(n - i);
+        period
     }
 
     fn next_move(&mut self) -> Movement {
@@ -335,44 +405,55 @@ impl<'a> FallingRocks<'a> {
     }
 }
 
-pub fn day17(content: String) {
-    println!();
-    println!("==== Day 17 ====");
-    let rocks = ROCKS
+pub(crate) fn parse_rocks() -> Vec<Rock> {
+    ROCKS
         .split("\n\n")
         .map(|x| x.parse::<Rock>().unwrap())
-        .collect_vec();
-    let movements = content
+        .collect_vec()
+}
+
+/// Parses the jet pattern, ignoring the line break and any whitespace around it
+pub(crate) fn parse_movements(content: &str) -> anyhow::Result<Vec<Movement>> {
+    content
+        .trim()
         .chars()
-        .map(|x| Movement::try_from(x).unwrap())
-        .collect_vec();
+        .enumerate()
+        .map(|(i, x)| {
+            Movement::try_from(x).with_context(|| format!("invalid jet '{}' at {}", x, i + 1))
+        })
+        .collect()
+}
+
+pub fn day17(content: String) -> anyhow::Result<DayResult> {
+    let mut part1 = String::new();
+    let mut part2 = String::new();
+    let rocks = parse_rocks();
+    let movements = parse_movements(&content)?;
 
-    println!("Part 1");
     let mut falling_rocks = FallingRocks::new(7, &rocks, &movements);
     falling_rocks.drop_n_rocks(2022);
-    println!("Height: {}", falling_rocks.current_height());
+    writeln!(part1, "Height: {}", falling_rocks.current_height())?;
 
-    println!();
-    println!("Part 2");
     let mut falling_rocks = FallingRocks::new(7, &rocks, &movements);
-    falling_rocks.drop_n_rocks_with_period_search(1000000000000, 1000000);
-    println!("Height: {}", falling_rocks.current_height());
+    falling_rocks.drop_n_rocks_with_period_search(1000000000000);
+    writeln!(part2, "Height: {}", falling_rocks.current_height())?;
+
+    Ok(DayResult::new(part1, Some(part2)))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::load_example;
     use itertools::Itertools;
 
-    const EXAMPLE: &'static str = r#">>><<><>><<<>><>>><<<>>><<<><<<>><>><<>>"#;
-
     #[test]
     fn test_part_1() {
         let rocks = ROCKS
             .split("\n\n")
             .map(|x| x.parse::<Rock>().unwrap())
             .collect_vec();
-        let movements = EXAMPLE
+        let movements = load_example(17)
             .chars()
             .map(|x| Movement::try_from(x).unwrap())
             .collect_vec();
@@ -381,18 +462,165 @@ mod tests {
         assert_eq!(falling_rocks.current_height(), 3068);
     }
 
+    #[test]
+    fn test_height_after() {
+        let rocks = ROCKS
+            .split("\n\n")
+            .map(|x| x.parse::<Rock>().unwrap())
+            .collect_vec();
+        let movements = load_example(17)
+            .chars()
+            .map(|x| Movement::try_from(x).unwrap())
+            .collect_vec();
+
+        let mut falling_rocks = FallingRocks::new(7, &rocks, &movements);
+        assert_eq!(falling_rocks.height_after(2022), 3068);
+
+        let mut falling_rocks = FallingRocks::new(7, &rocks, &movements);
+        assert_eq!(falling_rocks.height_after(1), 1);
+        assert_eq!(falling_rocks.height_after(9), 17);
+        assert_eq!(falling_rocks.height_after(2012), 3068);
+    }
+
+    #[test]
+    fn test_custom_rocks() {
+        let rocks = "##\n\n#\n#"
+            .split("\n\n")
+            .map(|x| x.parse::<Rock>().unwrap())
+            .collect_vec();
+        assert_eq!(rocks.len(), 2);
+        assert_eq!((rocks[0].width, rocks[0].height), (2, 1));
+        assert_eq!((rocks[1].width, rocks[1].height), (1, 2));
+
+        // Every rock is pushed against the left wall and lands on the previous one, so each
+        // horizontal rock adds one row and each vertical rock two
+        let movements = [Movement::Left];
+        let mut falling_rocks = FallingRocks::new(4, &rocks, &movements);
+        assert_eq!(falling_rocks.height_after(10), 5 + 5 * 2);
+        assert_eq!(
+            falling_rocks.map.top_rows(12, 15),
+            [
+                [true, true, false, false],
+                [true, false, false, false],
+                [true, false, false, false]
+            ]
+            .concat()
+        );
+    }
+
     #[test]
     fn test_part_2() {
         let rocks = ROCKS
             .split("\n\n")
             .map(|x| x.parse::<Rock>().unwrap())
             .collect_vec();
-        let movements = EXAMPLE
+        let movements = load_example(17)
             .chars()
             .map(|x| Movement::try_from(x).unwrap())
             .collect_vec();
         let mut falling_rocks = FallingRocks::new(7, &rocks, &movements);
-        falling_rocks.drop_n_rocks_with_period_search(1000000000000, 1000000);
+        falling_rocks.drop_n_rocks_with_period_search(1000000000000);
         assert_eq!(falling_rocks.current_height(), 1514285714288);
     }
+
+    #[test]
+    fn test_detected_period() {
+        let rocks = ROCKS
+            .split("\n\n")
+            .map(|x| x.parse::<Rock>().unwrap())
+            .collect_vec();
+        let movements = load_example(17)
+            .chars()
+            .map(|x| Movement::try_from(x).unwrap())
+            .collect_vec();
+
+        let mut falling_rocks = FallingRocks::new(7, &rocks, &movements);
+        let period = falling_rocks.drop_n_rocks_with_period_search(1000000000000);
+        assert_eq!(period, Some((35, 53)));
+
+        // Too few rocks to see a full period
+        let mut falling_rocks = FallingRocks::new(7, &rocks, &movements);
+        assert_eq!(falling_rocks.drop_n_rocks_with_period_search(100), None);
+        assert_eq!(falling_rocks.current_height(), 157);
+    }
+
+    #[test]
+    fn test_period_search_short_patterns() {
+        let rocks = ROCKS
+            .split("\n\n")
+            .map(|x| x.parse::<Rock>().unwrap())
+            .collect_vec();
+
+        // Short jet patterns make the highest fall keep changing long after the first rocks
+        for pattern in [">", "<", "<>", "<<>", ">><<<", &load_example(17)] {
+            let movements = pattern
+                .chars()
+                .map(|x| Movement::try_from(x).unwrap())
+                .collect_vec();
+
+            let mut falling_rocks = FallingRocks::new(7, &rocks, &movements);
+            falling_rocks.drop_n_rocks(5000);
+            let mut searched = FallingRocks::new(7, &rocks, &movements);
+            searched.drop_n_rocks_with_period_search(5000);
+            assert_eq!(searched.current_height(), falling_rocks.current_height());
+        }
+    }
+
+    #[test]
+    fn test_period_search_small_n() {
+        let rocks = parse_rocks();
+        for pattern in ["<>", &load_example(17)] {
+            let movements = parse_movements(pattern).unwrap();
+
+            // The period can also be found on the very last rock
+            let mut falling_rocks = FallingRocks::new(7, &rocks, &movements);
+            for n in 1..=600 {
+                falling_rocks.drop_n_rocks(1);
+                let mut searched = FallingRocks::new(7, &rocks, &movements);
+                searched.drop_n_rocks_with_period_search(n);
+                assert_eq!(
+                    searched.current_height(),
+                    falling_rocks.current_height(),
+                    "{} rocks",
+                    n
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_prune_below_floor() {
+        let rocks = parse_rocks();
+        let movements = parse_movements(&load_example(17)).unwrap();
+
+        let mut unpruned = FallingRocks::new(7, &rocks, &movements);
+        unpruned.drop_n_rocks(5000);
+
+        let mut pruned = FallingRocks::new(7, &rocks, &movements);
+        for _ in 0..50 {
+            pruned.drop_n_rocks(100);
+            pruned.map.prune_below_floor();
+        }
+        assert_eq!(pruned.current_height(), unpruned.current_height());
+        assert!(pruned.map.floor > 0);
+        assert!(pruned.map.fallen_rocks.len() < unpruned.map.fallen_rocks.len() / 10);
+    }
+
+    #[test]
+    fn test_day17_output() {
+        let output = day17(load_example(17)).unwrap();
+        assert!(output.part1.contains("Height: 3068"));
+    }
+
+    #[test]
+    fn test_trailing_whitespace() {
+        let output = day17(format!("{}\n", load_example(17))).unwrap();
+        assert!(output.part1.contains("Height: 3068"));
+
+        let movements = parse_movements(&format!("{}  \r\n", load_example(17))).unwrap();
+        assert_eq!(movements, parse_movements(&load_example(17)).unwrap());
+
+        let err = parse_movements("<>x<").unwrap_err();
+        assert_eq!(format!("{:#}", err), "invalid jet 'x' at 3: invalid char");
+    }
 }
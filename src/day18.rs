@@ -1,8 +1,10 @@
+use crate::days::DayResult;
 use anyhow::{anyhow, Context};
 use nom::bytes::complete::tag;
 use nom::character::complete;
 use nom::multi::separated_list1;
 use nom::{Finish, IResult};
+use std::fmt::Write;
 use std::str::FromStr;
 
 type Point3 = na::Point3<i64>;
@@ -23,6 +25,11 @@ struct Grid {
     width: usize,
     height: usize,
     length: usize,
+    /// Added to input coordinates to get the position in the grid
+    offset: Vector3,
+    /// Sides of filled tiles reached by all flood fills so far, like the `outside` tiles they
+    /// add up over several fills
+    outside_sides: usize,
 }
 
 impl Grid {
@@ -33,6 +40,8 @@ impl Grid {
             width,
             height,
             length,
+            offset: Vector3::zeros(),
+            outside_sides: 0,
         }
     }
 
@@ -84,6 +93,11 @@ impl Grid {
     }
 
     fn count_open_sides(&self) -> usize {
+        self.count_open_sides_with(NEIGHBORS)
+    }
+
+    /// Counts the empty neighbors of all filled tiles, with neighbors given by `offsets`
+    fn count_open_sides_with(&self, offsets: &[Vector3]) -> usize {
         let mut sides = 0;
         for z in 0..self.length {
             for y in 0..self.height {
@@ -95,16 +109,16 @@ impl Grid {
                         continue;
                     }
 
-                    sides += self.count_empty_neighbors(&pos);
+                    sides += self.count_empty_neighbors(&pos, offsets);
                 }
             }
         }
         sides
     }
 
-    fn count_empty_neighbors(&self, pos: &Point3) -> usize {
+    fn count_empty_neighbors(&self, pos: &Point3, offsets: &[Vector3]) -> usize {
         let mut count = 0;
-        for offset in NEIGHBORS {
+        for offset in offsets {
             let offset_pos = pos + offset;
 
             // Is empty
@@ -115,7 +129,16 @@ impl Grid {
         count
     }
 
+    /// Sides of filled tiles that are reachable from the outside, as counted by
+    /// `flood_fill_outside`
     fn count_outside_sides(&self) -> usize {
+        self.outside_sides
+    }
+
+    /// Like `count_outside_sides`, but checks the neighbors of all filled tiles. Also counts
+    /// sides facing out of the grid.
+    #[allow(dead_code)]
+    fn count_outside_sides_scan(&self) -> usize {
         let mut sides = 0;
         for z in 0..self.length {
             for y in 0..self.height {
@@ -147,6 +170,8 @@ impl Grid {
         count
     }
 
+    /// Marks all empty tiles reachable from `start_pos` as outside, adding the sides of filled
+    /// tiles found along the way to `outside_sides`
     fn flood_fill_outside(&mut self, start_pos: &Point3) {
         let mut stack = vec![*start_pos];
 
@@ -156,8 +181,9 @@ impl Grid {
                 continue;
             }
 
-            // Check if pos is filled
+            // Check if pos is filled, every outside tile pushes each neighbor only once
             if self.get_filled(&pos) {
+                self.outside_sides += 1;
                 continue;
             }
 
@@ -175,6 +201,46 @@ impl Grid {
         }
     }
 
+    /// Number of separate droplets, where filled tiles sharing a face belong to the same droplet
+    #[allow(dead_code)]
+    fn count_components(&self) -> usize {
+        let mut visited = vec![false; self.tiles.len()];
+        let mut components = 0;
+        for start in 0..self.tiles.len() {
+            if !self.tiles[start] || visited[start] {
+                continue;
+            }
+            components += 1;
+
+            let mut stack = vec![self.index_to_pos(start)];
+            while let Some(pos) = stack.pop() {
+                let Some(index) = self.index(&pos) else {
+                    continue;
+                };
+
+                // Empty or already part of the component
+                if !self.tiles[index] || visited[index] {
+                    continue;
+                }
+
+                visited[index] = true;
+
+                // Add all neighbors
+                for neighbor in NEIGHBORS {
+                    stack.push(pos + neighbor);
+                }
+            }
+        }
+        components
+    }
+
+    fn index_to_pos(&self, index: usize) -> Point3 {
+        let x = index % self.width;
+        let y = index / self.width % self.height;
+        let z = index / (self.width * self.height);
+        Point3::new(x as i64, y as i64, z as i64)
+    }
+
     #[allow(dead_code)]
     fn print_filled_sides(&self) {
         for z in 0..self.length {
@@ -182,7 +248,7 @@ impl Grid {
                 for x in 0..self.width {
                     let pos = Point3::new(x as i64, y as i64, z as i64);
                     if self.get_filled(&pos) {
-                        println!("{}", pos);
+                        println!("{}", pos - self.offset);
                     }
                 }
             }
@@ -197,32 +263,25 @@ impl FromStr for Grid {
         let (_, positions) = separated_list1(tag("\n"), parse_pos)(input)
             .finish()
             .map_err(|_| anyhow!("could not parse list"))?;
-        let max_x: usize = positions
-            .iter()
-            .map(|p| p.x)
-            .max()
-            .context("no points in grid")?
-            .try_into()?;
-        let max_y: usize = positions
+        let min = positions
             .iter()
-            .map(|p| p.y)
-            .max()
-            .context("no points in grid")?
-            .try_into()?;
-        let max_z: usize = positions
+            .copied()
+            .reduce(|a, b| a.inf(&b))
+            .context("no points in grid")?;
+        let max = positions
             .iter()
-            .map(|p| p.z)
-            .max()
-            .context("no points in grid")?
-            .try_into()?;
-        let width = max_x + 2;
-        let height = max_y + 2;
-        let length = max_z + 2;
+            .copied()
+            .reduce(|a, b| a.sup(&b))
+            .context("no points in grid")?;
 
-        let mut grid = Grid::new(width, height, length);
+        // Leave an empty layer around all points, so the flood fill can reach every side
+        let offset = Vector3::new(1, 1, 1) - min.coords;
+        let size = max - min + Vector3::new(3, 3, 3);
+        let mut grid = Grid::new(size.x as usize, size.y as usize, size.z as usize);
+        grid.offset = offset;
 
         for position in positions {
-            grid.set_filled(&position, true);
+            grid.set_filled(&(position + offset), true);
         }
 
         grid.flood_fill_outside(&Point3::new(0, 0, 0));
@@ -240,24 +299,24 @@ fn parse_pos(input: &str) -> IResult<&str, Point3> {
     Ok((input, Point3::new(x, y, z)))
 }
 
-pub fn day18(content: String) {
-    println!();
-    println!("==== Day 18 ====");
-    let grid = content.parse::<Grid>().unwrap();
+pub fn day18(content: String) -> anyhow::Result<DayResult> {
+    let mut part1 = String::new();
+    let mut part2 = String::new();
+    let grid = content.parse::<Grid>()?;
 
-    println!("Part 1");
-    println!("Sides: {}", grid.count_open_sides());
+    writeln!(part1, "Sides: {}", grid.count_open_sides())?;
 
-    println!();
-    println!("Part 2");
-    println!("Sides: {}", grid.count_outside_sides());
+    writeln!(part2, "Sides: {}", grid.count_outside_sides())?;
+
+    Ok(DayResult::new(part1, Some(part2)))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::bfs;
 
-    const EXAMPLE: &'static str = r#"2,2,2
+    const EXAMPLE: &str = r#"2,2,2
 1,2,2
 3,2,2
 2,1,2
@@ -286,15 +345,129 @@ mod tests {
         assert_eq!(grid.count_open_sides(), 10);
     }
 
+    #[test]
+    fn test_count_components() {
+        let mut grid = Grid::new(6, 6, 6);
+        grid.set_filled(&Point3::new(1, 1, 1), true);
+        grid.set_filled(&Point3::new(4, 4, 4), true);
+        assert_eq!(grid.count_components(), 2);
+
+        grid.set_filled(&Point3::new(2, 1, 1), true);
+        grid.set_filled(&Point3::new(2, 2, 2), true);
+        assert_eq!(grid.count_components(), 3);
+
+        // The main droplet, 2,2,6 and the four cubes around the air pocket at 2,2,5 only touch
+        // on edges
+        let grid = EXAMPLE.parse::<Grid>().unwrap();
+        assert_eq!(grid.count_components(), 6);
+    }
+
     #[test]
     fn test_part_1() {
         let grid = EXAMPLE.parse::<Grid>().unwrap();
         assert_eq!(grid.count_open_sides(), 64);
     }
 
+    #[test]
+    fn test_open_sides_with_edges() {
+        let grid = EXAMPLE.parse::<Grid>().unwrap();
+        assert_eq!(grid.count_open_sides_with(NEIGHBORS), 64);
+
+        // Faces and edges, but not corners
+        let offsets = (-1..=1)
+            .flat_map(|x| (-1..=1).flat_map(move |y| (-1..=1).map(move |z| Vector3::new(x, y, z))))
+            .filter(|x| (1..=2).contains(&x.abs().sum()))
+            .collect::<Vec<_>>();
+        assert_eq!(offsets.len(), 18);
+        assert_eq!(grid.count_open_sides_with(&offsets), 172);
+    }
+
     #[test]
     fn test_part_2() {
         let grid = EXAMPLE.parse::<Grid>().unwrap();
         assert_eq!(grid.count_outside_sides(), 58);
     }
+
+    #[test]
+    fn test_cached_outside_sides() {
+        let grid = EXAMPLE.parse::<Grid>().unwrap();
+        assert_eq!(grid.outside_sides, 58);
+        assert_eq!(grid.count_outside_sides_scan(), grid.count_outside_sides());
+    }
+
+    /// Test oracle for `Grid::flood_fill_outside`, walking the empty tiles with `utils::bfs`
+    /// instead of a stack. No tile is a goal, so the search visits every empty tile reachable
+    /// from the start and `successors` sees each of them exactly once.
+    fn flood_fill_outside_bfs(grid: &mut Grid, start_pos: &Point3) {
+        if grid.index(start_pos).is_none() || grid.get_filled(start_pos) {
+            return;
+        }
+
+        let mut reached = vec![];
+        let mut sides = 0;
+        bfs(
+            *start_pos,
+            |pos| {
+                reached.push(*pos);
+                let (filled, empty): (Vec<_>, Vec<_>) = NEIGHBORS
+                    .iter()
+                    .map(|offset| pos + offset)
+                    .filter(|pos| grid.index(pos).is_some())
+                    .partition(|pos| grid.get_filled(pos));
+                sides += filled.len();
+                empty
+            },
+            |_| false,
+        );
+
+        for pos in reached {
+            grid.set_outside(&pos, true);
+        }
+        grid.outside_sides += sides;
+    }
+
+    #[test]
+    fn test_flood_fill_outside_bfs() {
+        let grid = EXAMPLE.parse::<Grid>().unwrap();
+        let mut bfs_grid = EXAMPLE.parse::<Grid>().unwrap();
+        bfs_grid.outside.fill(false);
+        bfs_grid.outside_sides = 0;
+
+        flood_fill_outside_bfs(&mut bfs_grid, &Point3::new(0, 0, 0));
+        assert_eq!(bfs_grid.outside, grid.outside);
+        assert_eq!(bfs_grid.count_outside_sides(), 58);
+    }
+
+    #[test]
+    fn test_shifted_coordinates() {
+        let shifted = EXAMPLE
+            .lines()
+            .map(|line| {
+                line.split(',')
+                    .map(|x| (x.parse::<i64>().unwrap() - 5).to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert!(shifted.starts_with("-3,-3,-3\n"));
+
+        let grid = shifted.parse::<Grid>().unwrap();
+        assert_eq!(grid.offset, Vector3::new(5, 5, 5));
+        assert_eq!(grid.count_open_sides(), 64);
+        assert_eq!(grid.count_outside_sides(), 58);
+    }
+
+    #[test]
+    fn test_cube_on_boundary() {
+        let grid = "0,0,0".parse::<Grid>().unwrap();
+        assert_eq!(grid.count_open_sides(), 6);
+        assert_eq!(grid.count_outside_sides(), 6);
+    }
+
+    #[test]
+    fn test_day18_output() {
+        let output = day18(EXAMPLE.to_string()).unwrap();
+        assert!(output.part1.contains("Sides: 64"));
+    }
 }
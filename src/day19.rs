@@ -1,4 +1,5 @@
-use crate::utils::dfs;
+use crate::days::DayResult;
+use crate::utils::dfs_with_path;
 use derivative::Derivative;
 use itertools::Itertools;
 use nom::branch::alt;
@@ -7,6 +8,8 @@ use nom::character::complete::{multispace1, space1, u64};
 use nom::combinator::fail;
 use nom::multi::separated_list1;
 use nom::{Finish, IResult};
+use std::cmp::Reverse;
+use std::fmt::Write;
 use std::ops::AddAssign;
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -53,11 +56,24 @@ fn parse_robot(input: &str) -> IResult<&str, Robot> {
 }
 
 #[derive(Debug, Eq, PartialEq, Hash)]
-struct Blueprint {
+pub(crate) struct Blueprint {
     id: u64,
     robots: Vec<Robot>,
 }
 
+impl Blueprint {
+    /// Highest amount of `resource` any robot costs
+    fn max_cost(&self, resource: &Resource) -> u64 {
+        self.robots
+            .iter()
+            .flat_map(|x| &x.costs)
+            .filter(|(x, _)| x == resource)
+            .map(|(_, cost)| *cost)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
 fn parse_blueprint(input: &str) -> IResult<&str, Blueprint> {
     let (input, _) = tag("Blueprint ")(input)?;
     let (input, id) = u64(input)?;
@@ -67,7 +83,7 @@ fn parse_blueprint(input: &str) -> IResult<&str, Blueprint> {
     Ok((input, Blueprint { id, robots }))
 }
 
-fn parse_blueprints(input: &str) -> Vec<Blueprint> {
+pub(crate) fn parse_blueprints(input: &str) -> Vec<Blueprint> {
     let (input, blueprints) = separated_list1(multispace1, parse_blueprint)(input)
         .finish()
         .unwrap();
@@ -137,6 +153,9 @@ impl AddAssign for Storage {
     }
 }
 
+/// States `Simulator::greedy_lower_bound` keeps every minute
+const GREEDY_BEAM_WIDTH: usize = 20;
+
 #[derive(Clone, Derivative)]
 #[derivative(Debug, Eq, PartialEq, Hash)]
 struct Simulator<'a> {
@@ -145,6 +164,8 @@ struct Simulator<'a> {
     time: usize,
     storage: Storage,
     production: Storage,
+    /// Robots (by index) that could have been built last minute, but weren't
+    skipped: u8,
 }
 
 impl<'a> Simulator<'a> {
@@ -157,41 +178,69 @@ impl<'a> Simulator<'a> {
                 ore: 1,
                 ..Default::default()
             },
+            skipped: 0,
         }
     }
 
     fn successors(&self) -> impl Iterator<Item = Simulator<'a>> + '_ {
-        // Don't build anything
-        std::iter::once(self.next()).chain(
+        // Don't build anything, remembering what could have been built instead
+        let mut wait = self.next();
+        wait.skipped = self
+            .blueprint
+            .robots
+            .iter()
+            .enumerate()
+            .filter(|(_, robot)| self.can_afford(robot))
+            .fold(0, |skipped, (index, _)| skipped | (1 << index));
+
+        std::iter::once(wait).chain(
             // Build robots
             self.blueprint
                 .robots
                 .iter()
+                .enumerate()
                 // Basically a if x return, but with the same return type
-                .filter_map(|robot| self.next_with_built_robot(robot)),
+                .filter_map(|(index, robot)| self.next_with_built_robot(index, robot)),
         )
     }
 
-    fn next_with_built_robot(&self, robot: &Robot) -> Option<Self> {
-        if robot
+    fn next_with_built_robot(&self, index: usize, robot: &Robot) -> Option<Self> {
+        // Building it a minute later would only have been worse
+        if self.skipped & (1 << index) != 0 {
+            return None;
+        }
+
+        // Only geodes are never spent, more robots than the most expensive recipe can use are
+        // useless for anything else
+        if robot.produces != Resource::Geode
+            && self.production.resource(&robot.produces) >= self.blueprint.max_cost(&robot.produces)
+        {
+            return None;
+        }
+
+        if !self.can_afford(robot) {
+            return None;
+        }
+
+        let mut next = self.next();
+        for (resource, cost) in &robot.costs {
+            next.storage.remove_resource(resource, *cost);
+        }
+        next.production.add_resource(&robot.produces, 1);
+        Some(next)
+    }
+
+    fn can_afford(&self, robot: &Robot) -> bool {
+        robot
             .costs
             .iter()
             .all(|(resource, cost)| self.storage.resource(resource) >= *cost)
-        {
-            let mut next = self.next();
-            for (resource, cost) in &robot.costs {
-                next.storage.remove_resource(resource, *cost);
-            }
-            next.production.add_resource(&robot.produces, 1);
-            Some(next)
-        } else {
-            None
-        }
     }
 
     fn next(&self) -> Self {
         let mut clone = self.clone();
         clone.time += 1;
+        clone.skipped = 0;
         clone.storage += clone.production.clone();
         clone
     }
@@ -200,84 +249,187 @@ impl<'a> Simulator<'a> {
         self.storage.geode
     }
 
+    /// Geodes opened by a greedy beam search, which only keeps the `GREEDY_BEAM_WIDTH` states
+    /// that look most promising every minute. Quick, and usually close to the optimum, but it
+    /// can miss it.
+    fn greedy_lower_bound(&self, max_time: usize) -> u64 {
+        let mut beam = vec![self.clone()];
+        while beam.first().is_some_and(|x| x.time < max_time) {
+            beam = beam
+                .iter()
+                .flat_map(|x| x.successors())
+                .unique()
+                .sorted_unstable_by_key(|x| {
+                    // Geodes opened at the end without building anything else, then the robots
+                    // needed to get there
+                    let remaining = (max_time - x.time) as u64;
+                    Reverse((
+                        x.score() + remaining * x.production.geode,
+                        x.production.obsidian,
+                        x.production.clay,
+                        x.production.ore,
+                    ))
+                })
+                .take(GREEDY_BEAM_WIDTH)
+                .collect_vec();
+        }
+        beam.iter().map(|x| x.score()).max().unwrap_or(0)
+    }
+
     fn best_possible_score(&self, max_time: usize) -> u64 {
-        let mut score = self.score();
-        let mut production = self.production.geode;
-        for _ in self.time..max_time {
-            score += production;
+        let remaining = max_time.saturating_sub(self.time) as u64;
 
-            // Add a new geode bot every minute
-            production += 1;
-        }
-        score
+        // Add a new geode bot every minute
+        self.score()
+            + remaining * self.production.geode
+            + remaining * remaining.saturating_sub(1) / 2
     }
 }
 
-fn score_blueprints(blueprints: &[Blueprint], max_time: usize, with_quality: bool) -> u64 {
-    let mut total_score = 0;
-    for blueprint in blueprints {
-        let simulator = Simulator::new(blueprint);
-        let best_score = dfs(
-            simulator,
-            |x| x.successors().collect_vec(),
-            |x| x.score(),
-            |x| x.best_possible_score(max_time),
-            |x| x.time >= max_time,
-        );
-        if with_quality {
-            total_score += best_score * blueprint.id;
-        } else if total_score == 0 {
-            total_score = best_score;
-        } else {
-            total_score *= best_score;
-        }
+/// Returns the maximum number of geodes each blueprint can open, as `(blueprint_id, max_geodes)`
+fn blueprint_geodes(blueprints: &[Blueprint], max_time: usize) -> Vec<(u64, u64)> {
+    blueprints
+        .iter()
+        .map(|blueprint| {
+            let simulator = Simulator::new(blueprint);
+            let (best_score, _) = dfs_with_path(
+                simulator.clone(),
+                simulator.greedy_lower_bound(max_time),
+                None,
+                |x| x.successors().collect_vec(),
+                |x| x.score(),
+                |x| x.best_possible_score(max_time),
+                |x| x.time >= max_time,
+            );
+            (blueprint.id, best_score)
+        })
+        .collect()
+}
+
+pub(crate) fn score_blueprints(
+    blueprints: &[Blueprint],
+    max_time: usize,
+    with_quality: bool,
+) -> u64 {
+    score_geodes(&blueprint_geodes(blueprints, max_time), with_quality)
+}
+
+/// Scores the result of `blueprint_geodes`, either as the sum of the quality levels or as the
+/// product of the geodes
+fn score_geodes(geodes: &[(u64, u64)], with_quality: bool) -> u64 {
+    if with_quality {
+        geodes.iter().map(|(id, geodes)| id * geodes).sum()
+    } else {
+        geodes.iter().map(|(_, geodes)| geodes).product()
     }
-    total_score
 }
 
-pub fn day19(content: String) {
-    println!();
-    println!("==== Day 19 ====");
-    let blueprints = parse_blueprints(&content);
-
-    println!("Part 1");
-    println!("Skipping part 1");
-    // println!("Score: {}", score_blueprints(&blueprints, 24, true));
-
-    println!();
-    println!("Part 2");
-    println!("Skipping part 2");
-    // println!(
-    //     "Score: {}",
-    //     score_blueprints(&blueprints.into_iter().take(3).collect_vec(), 32, false)
-    // );
+pub fn day19(content: String) -> anyhow::Result<DayResult> {
+    Ok(DayResult::new(part1(&content)?, Some(part2(&content)?)))
+}
+
+pub fn part1(content: &str) -> anyhow::Result<String> {
+    let blueprints = parse_blueprints(content);
+
+    let geodes = blueprint_geodes(&blueprints, 24);
+    let mut output = String::new();
+    for (id, geodes) in &geodes {
+        writeln!(output, "Blueprint {}: {} geodes", id, geodes)?;
+    }
+    write!(output, "Score: {}", score_geodes(&geodes, true))?;
+    Ok(output)
+}
+
+pub fn part2(content: &str) -> anyhow::Result<String> {
+    let blueprints = parse_blueprints(content);
+    Ok(format!(
+        "Score: {}",
+        score_blueprints(&blueprints.into_iter().take(3).collect_vec(), 32, false)
+    ))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::{branch_and_bound_expanded, dfs_expanded, load_example};
 
-    const EXAMPLE: &'static str = r#"Blueprint 1:
-  Each ore robot costs 4 ore.
-  Each clay robot costs 2 ore.
-  Each obsidian robot costs 3 ore and 14 clay.
-  Each geode robot costs 2 ore and 7 obsidian.
+    #[test]
+    fn test_blueprint_geodes() {
+        let blueprints = parse_blueprints(&load_example(19));
+        assert_eq!(blueprint_geodes(&blueprints, 24), vec![(1, 9), (2, 12)]);
+    }
+
+    #[test]
+    fn test_greedy_lower_bound() {
+        let blueprints = parse_blueprints(&load_example(19));
+        for (blueprint, optimum) in blueprints.iter().zip([9, 12]) {
+            let simulator = Simulator::new(blueprint);
+            let greedy = simulator.greedy_lower_bound(24);
+            assert!(greedy > 0 && greedy <= optimum);
+
+            let search = |initial_best| {
+                dfs_expanded(
+                    simulator.clone(),
+                    initial_best,
+                    |x| x.successors().collect_vec(),
+                    |x| x.score(),
+                    |x| x.best_possible_score(24),
+                    |x| x.time >= 24,
+                )
+            };
+            let (unseeded, unseeded_expanded) = search(0);
+            let (seeded, seeded_expanded) = search(greedy);
+            assert_eq!((unseeded, seeded), (optimum, optimum));
+            // Less of the graph has to be searched to find the optimum
+            assert!(seeded_expanded < unseeded_expanded);
+        }
+        assert_eq!(blueprint_geodes(&blueprints, 24), vec![(1, 9), (2, 12)]);
+    }
 
-Blueprint 2:
-  Each ore robot costs 2 ore.
-  Each clay robot costs 3 ore.
-  Each obsidian robot costs 3 ore and 8 clay.
-  Each geode robot costs 3 ore and 12 obsidian."#;
+    #[test]
+    fn test_branch_and_bound() {
+        let blueprints = parse_blueprints(&load_example(19));
+        for (blueprint, optimum) in blueprints.iter().zip([9, 12]) {
+            let simulator = Simulator::new(blueprint);
+            let (depth_first, depth_first_expanded) = dfs_expanded(
+                simulator.clone(),
+                0,
+                |x| x.successors().collect_vec(),
+                |x| x.score(),
+                |x| x.best_possible_score(24),
+                |x| x.time >= 24,
+            );
+            let (best_first, best_first_expanded) = branch_and_bound_expanded(
+                simulator,
+                |x| x.successors().collect_vec(),
+                |x| x.score(),
+                |x| x.best_possible_score(24),
+                |x| x.time >= 24,
+            );
+            assert_eq!((depth_first, best_first), (optimum, optimum));
+            // Good leaves are found early, so fewer states are left to expand
+            assert!(best_first_expanded < depth_first_expanded);
+        }
+    }
 
     #[test]
     fn test_part_1() {
-        let blueprints = parse_blueprints(EXAMPLE);
+        let blueprints = parse_blueprints(&load_example(19));
         assert_eq!(score_blueprints(&blueprints, 24, true), 33)
     }
 
     #[test]
     fn test_part_2() {
-        let blueprints = parse_blueprints(EXAMPLE).into_iter().take(3).collect_vec();
+        let blueprints = parse_blueprints(&load_example(19))
+            .into_iter()
+            .take(3)
+            .collect_vec();
         assert_eq!(score_blueprints(&blueprints, 32, false), 56 * 62)
     }
+
+    #[test]
+    fn test_day19_output() {
+        let output = day19(load_example(19)).unwrap();
+        assert!(output.part1.contains("Score: 33"));
+    }
 }
@@ -1,5 +1,7 @@
-use anyhow::bail;
+use crate::days::DayResult;
+use anyhow::{bail, Context};
 use itertools::Itertools;
+use std::fmt::Write;
 use std::str::FromStr;
 
 enum Outcome {
@@ -92,31 +94,102 @@ impl FromStr for Hand {
     }
 }
 
-pub fn day2(content: String) -> anyhow::Result<()> {
-    println!("Day 1");
-    let hands = content
-        .split('\n')
-        .map(|x| {
-            let values = x.split(' ').collect_vec();
-            assert_eq!(values.len(), 2);
-            let enemy = values[0].parse::<Hand>().unwrap();
-            let your_outcome = values[1].parse::<Outcome>().unwrap();
-            let you = your_outcome.achieve_outcome(&enemy);
+pub fn day2(content: String) -> anyhow::Result<DayResult> {
+    let mut part1 = String::new();
+    let mut part2 = String::new();
+    writeln!(part1, "Your score: {}", score_hands(&content)?)?;
+
+    writeln!(part2, "Your score: {}", score_outcomes(&content)?)?;
+
+    Ok(DayResult::new(part1, Some(part2)))
+}
 
-            (enemy, you)
+/// Splits each round into its two columns, which may be separated by any whitespace, skipping
+/// blank lines
+fn rounds(content: &str) -> impl Iterator<Item = anyhow::Result<(&str, &str)>> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, x)| !x.trim().is_empty())
+        .map(|(index, x)| {
+            x.split_whitespace()
+                .collect_tuple()
+                .with_context(|| format!("round '{}' on line {} must have 2 columns", x, index + 1))
         })
-        .collect_vec();
+}
 
-    let your_score: usize = hands
-        .iter()
-        .map(|(enemy, you)| calc_score(you, enemy))
-        .sum();
-    println!("Your score: {}", your_score);
-    println!();
+/// Scores all rounds, interpreting the second column as the hand you play
+fn score_hands(content: &str) -> anyhow::Result<usize> {
+    rounds(content)
+        .map(|round| {
+            let (enemy, you) = round?;
+            let enemy = enemy.parse::<Hand>()?;
+            let you = you.parse::<Hand>()?;
+            Ok(calc_score(&you, &enemy))
+        })
+        .sum()
+}
 
-    Ok(())
+/// Scores all rounds, interpreting the second column as the outcome you need to achieve
+fn score_outcomes(content: &str) -> anyhow::Result<usize> {
+    rounds(content)
+        .map(|round| {
+            let (enemy, your_outcome) = round?;
+            let enemy = enemy.parse::<Hand>()?;
+            let your_outcome = your_outcome.parse::<Outcome>()?;
+            let you = your_outcome.achieve_outcome(&enemy);
+            Ok(calc_score(&you, &enemy))
+        })
+        .sum()
 }
 
 fn calc_score(you: &Hand, enemy: &Hand) -> usize {
     you.score() + you.result(enemy).score()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = r#"A Y
+B X
+C Z"#;
+
+    #[test]
+    fn test_part_1() {
+        assert_eq!(score_hands(EXAMPLE).unwrap(), 15);
+    }
+
+    #[test]
+    fn test_part_2() {
+        assert_eq!(score_outcomes(EXAMPLE).unwrap(), 12);
+    }
+
+    #[test]
+    fn test_trailing_newline() {
+        let content = format!("{}\n", EXAMPLE);
+        assert_eq!(score_hands(&content).unwrap(), 15);
+        assert_eq!(score_outcomes(&content).unwrap(), 12);
+    }
+
+    #[test]
+    fn test_whitespace_separators() {
+        let content = "A\tY\nB  X\n  C Z ";
+        assert_eq!(score_hands(content).unwrap(), 15);
+        assert_eq!(score_outcomes(content).unwrap(), 12);
+
+        let err = score_hands("A Y\nB X Z").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "round 'B X Z' on line 2 must have 2 columns"
+        );
+        let err = score_outcomes("A").err().unwrap();
+        assert_eq!(err.to_string(), "round 'A' on line 1 must have 2 columns");
+    }
+
+    #[test]
+    fn test_day2_output() {
+        let output = day2(EXAMPLE.to_string()).unwrap();
+        assert!(output.part1.contains("Your score: 15"));
+    }
+}
@@ -1,48 +1,45 @@
+use crate::days::DayResult;
 use itertools::Itertools;
 
-fn parse_list(content: &str) -> Vec<i32> {
-    content.lines().map(|x| x.parse::<i32>().unwrap()).collect()
-}
-
-fn mix_list(list: &mut Vec<i32>) {
-    #[cfg(test)]
-    println!("{:?}", list);
-    let mut order = list.iter().cloned().rev().collect_vec();
+const DECRYPTION_KEY: i64 = 811589153;
 
-    while let Some(num) = order.pop() {
-        let (index, _) = list
-            .iter()
-            .find_position(|x| **x == num)
-            .expect("number not found");
-        move_in_direction(list, index, num);
-        #[cfg(test)]
-        println!("{:?}", list);
-    }
+fn parse_list(content: &str) -> Vec<i64> {
+    content.lines().map(|x| x.parse::<i64>().unwrap()).collect()
 }
 
-fn jump_in_direction(list: &mut Vec<i32>, mut from: usize, dir: i32) {
-    let value = list.remove(from);
-
-    let mut from = from as i32;
+/// Mixes the list `rounds` times, always moving the numbers in their original order
+fn mix_list_n(list: &[i64], rounds: usize) -> Vec<i64> {
+    if list.len() < 2 {
+        return list.to_vec();
+    }
 
-    let mut to = (from + dir).rem_euclid(list.len() as i32);
+    // Remember the original position of each number, as values aren't unique
+    let mut mixed = list.iter().copied().enumerate().collect_vec();
+    for _ in 0..rounds {
+        for original_index in 0..list.len() {
+            let (index, &(_, num)) = mixed
+                .iter()
+                .find_position(|(x, _)| *x == original_index)
+                .expect("number not found");
+            move_element(&mut mixed, index, num);
+        }
+    }
 
-    list.insert(to as usize, value);
+    mixed.into_iter().map(|(_, num)| num).collect()
 }
 
-fn move_in_direction(list: &mut Vec<i32>, mut from: usize, mut dir: i32) {
-    while dir != 0 {
-        // Mathematical modulo with list length
-        let mut to = (from as i32 + dir.signum()).rem_euclid(list.len() as i32) as usize;
-        dir -= dir.signum();
-
-        list.swap(from, to);
-
-        from = to;
-    }
+/// Moves the element at `from` by `dir` places, wrapping around the ends.
+///
+/// The list is circular, so an element moving past all `len - 1` others ends up in the same order
+/// again and only `dir` modulo `len - 1` matters. Which element ends up first in the list isn't
+/// relevant either, so the element can be inserted at its new position right away.
+fn move_element(list: &mut Vec<(usize, i64)>, from: usize, dir: i64) {
+    let element = list.remove(from);
+    let to = (from as i64 + dir).rem_euclid(list.len() as i64);
+    list.insert(to as usize, element);
 }
 
-fn calc_coordinates(list: &[i32]) -> i32 {
+fn calc_coordinates(list: &[i64]) -> i64 {
     let (zero_pos, _) = list.iter().find_position(|x| **x == 0).unwrap();
     let first = *list
         .get((zero_pos + 1000usize).rem_euclid(list.len()))
@@ -53,27 +50,46 @@ fn calc_coordinates(list: &[i32]) -> i32 {
     let third = *list
         .get((zero_pos + 3000usize).rem_euclid(list.len()))
         .unwrap();
-    return first + second + third;
+    first + second + third
 }
 
-pub fn day20(content: String) {
-    println!();
-    println!("==== Day 20 ====");
-    let mut list = parse_list(&content);
+pub fn day20(content: String) -> anyhow::Result<DayResult> {
+    Ok(DayResult::new(part1(&content)?, Some(part2(&content)?)))
+}
 
-    println!("Part 1");
-    mix_list(&mut list);
-    println!("Coordinates: {}", calc_coordinates(&list));
+pub fn part1(content: &str) -> anyhow::Result<String> {
+    let list = parse_list(content);
+    let mixed = mix_list_n(&list, 1);
+    Ok(format!("Coordinates: {}", calc_coordinates(&mixed)))
+}
 
-    println!();
-    println!("Part 2");
+pub fn part2(content: &str) -> anyhow::Result<String> {
+    let list = parse_list(content)
+        .into_iter()
+        .map(|x| x * DECRYPTION_KEY)
+        .collect_vec();
+    let list = mix_list_n(&list, 10);
+    Ok(format!("Coordinates: {}", calc_coordinates(&list)))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    const EXAMPLE: &'static str = r#"1
+    /// Moves the element one swap at a time, `move_element` does the same in one step
+    fn move_in_direction<T>(list: &mut [T], mut from: usize, mut dir: i64) {
+        while dir != 0 {
+            // Mathematical modulo with list length
+            let to = (from as i64 + dir.signum()).rem_euclid(list.len() as i64) as usize;
+            dir -= dir.signum();
+
+            list.swap(from, to);
+
+            from = to;
+        }
+    }
+
+    const EXAMPLE: &str = r#"1
 2
 -3
 3
@@ -83,13 +99,65 @@ mod tests {
 
     #[test]
     fn test_part_1() {
-        let mut list = parse_list(EXAMPLE);
-        mix_list(&mut list);
-        println!("{:?}", list);
-        // assert_eq!(&list, &[1, 2, -3, 4, 0, 3, -2]);
-        assert_eq!(calc_coordinates(&list), 3);
+        let list = parse_list(EXAMPLE);
+        let mixed = mix_list_n(&list, 1);
+        assert_eq!(starting_at_zero(&mixed), [0, 3, -2, 1, 2, -3, 4]);
+        assert_eq!(calc_coordinates(&mixed), 3);
+    }
+
+    #[test]
+    fn test_duplicates() {
+        // Looking up the second 1 by value would find the first one again and move it twice
+        let mixed = mix_list_n(&[1, 0, 1, 3], 1);
+        assert_eq!(starting_at_zero(&mixed), [0, 1, 3, 1]);
+    }
+
+    #[test]
+    fn test_move_element() {
+        let list = parse_list(EXAMPLE);
+        let mut moved = list.iter().copied().enumerate().collect_vec();
+        let mut swapped = moved.clone();
+        for original_index in 0..list.len() {
+            let (index, &(_, num)) = moved
+                .iter()
+                .find_position(|(x, _)| *x == original_index)
+                .unwrap();
+            move_element(&mut moved, index, num);
+
+            let (index, &(_, num)) = swapped
+                .iter()
+                .find_position(|(x, _)| *x == original_index)
+                .unwrap();
+            move_in_direction(&mut swapped, index, num);
+
+            let moved = moved.iter().map(|(_, x)| *x).collect_vec();
+            let swapped = swapped.iter().map(|(_, x)| *x).collect_vec();
+            assert_eq!(starting_at_zero(&moved), starting_at_zero(&swapped));
+        }
+    }
+
+    fn starting_at_zero(list: &[i64]) -> Vec<i64> {
+        let (zero_pos, _) = list.iter().find_position(|x| **x == 0).unwrap();
+        list[zero_pos..]
+            .iter()
+            .chain(&list[..zero_pos])
+            .copied()
+            .collect()
     }
 
     #[test]
-    fn test_part_2() {}
+    fn test_part_2() {
+        let list = parse_list(EXAMPLE)
+            .into_iter()
+            .map(|x| x * DECRYPTION_KEY)
+            .collect_vec();
+        let list = mix_list_n(&list, 10);
+        assert_eq!(calc_coordinates(&list), 1623178306);
+    }
+
+    #[test]
+    fn test_day20_output() {
+        let output = day20(EXAMPLE.to_string()).unwrap();
+        assert!(output.part1.contains("Coordinates: 3"));
+    }
 }
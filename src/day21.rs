@@ -1,22 +1,275 @@
-pub fn day21(content: String) {
-    println!();
-    println!("==== Day 20 ====");
+use crate::days::DayResult;
+use anyhow::{bail, Context};
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::str::FromStr;
 
-    println!("Part 1");
+const ROOT: &str = "root";
+const HUMAN: &str = "humn";
 
-    println!();
-    println!("Part 2");
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Op {
+    fn apply(&self, left: i64, right: i64) -> i64 {
+        match self {
+            Op::Add => left + right,
+            Op::Sub => left - right,
+            Op::Mul => left * right,
+            Op::Div => left / right,
+        }
+    }
+
+    /// Solves `target = x op right` for `x`
+    fn solve_left(&self, target: i64, right: i64) -> i64 {
+        match self {
+            Op::Add => target - right,
+            Op::Sub => target + right,
+            Op::Mul => target / right,
+            Op::Div => target * right,
+        }
+    }
+
+    /// Solves `target = left op x` for `x`
+    fn solve_right(&self, target: i64, left: i64) -> i64 {
+        match self {
+            Op::Add => target - left,
+            Op::Sub => left - target,
+            Op::Mul => target / left,
+            Op::Div => left / target,
+        }
+    }
+}
+
+impl FromStr for Op {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "+" => Op::Add,
+            "-" => Op::Sub,
+            "*" => Op::Mul,
+            "/" => Op::Div,
+            op => bail!("unsupported op '{}'", op),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Job {
+    Value(i64),
+    Operation(String, Op, String),
+}
+
+impl FromStr for Job {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(value) = s.parse::<i64>() {
+            return Ok(Job::Value(value));
+        }
+
+        let (left, op, right) = s
+            .split_whitespace()
+            .collect_tuple()
+            .context("expected a number or 'lhs op rhs'")?;
+        Ok(Job::Operation(
+            left.to_string(),
+            op.parse::<Op>()?,
+            right.to_string(),
+        ))
+    }
+}
+
+struct Monkeys {
+    jobs: HashMap<String, Job>,
+}
+
+impl Monkeys {
+    fn job(&self, name: &str) -> anyhow::Result<&Job> {
+        self.jobs
+            .get(name)
+            .with_context(|| format!("unknown monkey '{}'", name))
+    }
+
+    /// Number yelled by `name`, remembering every intermediate result in `cache`
+    fn evaluate(&self, name: &str, cache: &mut HashMap<String, i64>) -> anyhow::Result<i64> {
+        if let Some(&value) = cache.get(name) {
+            return Ok(value);
+        }
+
+        let value = match self.job(name)? {
+            Job::Value(value) => *value,
+            Job::Operation(left, op, right) => {
+                let left = self.evaluate(left, cache)?;
+                let right = self.evaluate(right, cache)?;
+                op.apply(left, right)
+            }
+        };
+        cache.insert(name.to_string(), value);
+        Ok(value)
+    }
+
+    fn root_value(&self) -> anyhow::Result<i64> {
+        self.evaluate(ROOT, &mut HashMap::new())
+    }
+
+    fn depends_on_human(&self, name: &str) -> anyhow::Result<bool> {
+        if name == HUMAN {
+            return Ok(true);
+        }
+
+        Ok(match self.job(name)? {
+            Job::Value(_) => false,
+            Job::Operation(left, _, right) => {
+                self.depends_on_human(left)? || self.depends_on_human(right)?
+            }
+        })
+    }
+
+    /// Number the human has to yell for both sides of `root` to be equal.
+    ///
+    /// Only one side of each operation may depend on the human, the other side is evaluated and
+    /// the operation inverted until the human is reached.
+    fn human_value(&self) -> anyhow::Result<i64> {
+        let mut cache = HashMap::new();
+        // Equality is the same as a difference of zero
+        let (mut name, mut target) = match self.job(ROOT)? {
+            Job::Operation(left, _, right) => {
+                self.solve_branch(left, Op::Sub, right, 0, &mut cache)?
+            }
+            Job::Value(_) => bail!("'{}' must compare two monkeys", ROOT),
+        };
+
+        while name != HUMAN {
+            (name, target) = match self.job(name)? {
+                Job::Operation(left, op, right) => {
+                    self.solve_branch(left, *op, right, target, &mut cache)?
+                }
+                Job::Value(_) => bail!("monkey '{}' doesn't depend on '{}'", name, HUMAN),
+            };
+        }
+        Ok(target)
+    }
+
+    /// Returns the side of `left op right` that depends on the human and the number it has to
+    /// yell for the operation to result in `target`
+    fn solve_branch<'a>(
+        &self,
+        left: &'a str,
+        op: Op,
+        right: &'a str,
+        target: i64,
+        cache: &mut HashMap<String, i64>,
+    ) -> anyhow::Result<(&'a str, i64)> {
+        match (self.depends_on_human(left)?, self.depends_on_human(right)?) {
+            (true, false) => Ok((left, op.solve_left(target, self.evaluate(right, cache)?))),
+            (false, true) => Ok((right, op.solve_right(target, self.evaluate(left, cache)?))),
+            _ => bail!(
+                "exactly one of '{}' and '{}' must depend on '{}'",
+                left,
+                right,
+                HUMAN
+            ),
+        }
+    }
+}
+
+impl FromStr for Monkeys {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let jobs = s
+            .lines()
+            .map(|line| {
+                let (name, job) = line
+                    .split_once(": ")
+                    .with_context(|| format!("expected 'name: job' in '{}'", line))?;
+                let job = job
+                    .parse::<Job>()
+                    .with_context(|| format!("could not parse job of '{}'", name))?;
+                Ok((name.to_string(), job))
+            })
+            .collect::<anyhow::Result<HashMap<String, Job>>>()?;
+        Ok(Self { jobs })
+    }
+}
+
+pub fn day21(content: String) -> anyhow::Result<DayResult> {
+    let mut part1 = String::new();
+    let mut part2 = String::new();
+    let monkeys = content.parse::<Monkeys>()?;
+
+    writeln!(part1, "Root yells: {}", monkeys.root_value()?)?;
+
+    writeln!(part2, "Human yells: {}", monkeys.human_value()?)?;
+
+    Ok(DayResult::new(part1, Some(part2)))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    const EXAMPLE: &'static str = r#""#;
+    const EXAMPLE: &str = r#"root: pppw + sjmn
+dbpl: 5
+cczh: sllz + lgvd
+zczc: 2
+ptdq: humn - dvpt
+dvpt: 3
+lfqf: 4
+humn: 5
+ljgn: 2
+sjmn: drzm * dbpl
+sllz: 4
+pppw: cczh / lfqf
+lgvd: ljgn * ptdq
+drzm: hmdt - zczc
+hmdt: 32"#;
+
+    #[test]
+    fn test_part_1() {
+        let monkeys = EXAMPLE.parse::<Monkeys>().unwrap();
+        assert_eq!(monkeys.root_value().unwrap(), 152);
+    }
 
     #[test]
-    fn test_part_1() {}
+    fn test_part_2() {
+        let mut monkeys = EXAMPLE.parse::<Monkeys>().unwrap();
+        let human = monkeys.human_value().unwrap();
+        assert_eq!(human, 301);
+
+        // Both sides of root match with that number
+        monkeys.jobs.insert(HUMAN.to_string(), Job::Value(human));
+        let mut cache = HashMap::new();
+        assert_eq!(
+            monkeys.evaluate("pppw", &mut cache).unwrap(),
+            monkeys.evaluate("sjmn", &mut cache).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_invalid_job() {
+        let err = "root: a ? b".parse::<Monkeys>().err().unwrap();
+        assert_eq!(
+            format!("{:#}", err),
+            "could not parse job of 'root': unsupported op '?'"
+        );
+
+        let monkeys = "root: a + b\na: 1".parse::<Monkeys>().unwrap();
+        let err = monkeys.root_value().err().unwrap();
+        assert_eq!(format!("{:#}", err), "unknown monkey 'b'");
+    }
 
     #[test]
-    fn test_part_2() {}
+    fn test_day21_output() {
+        let output = day21(EXAMPLE.to_string()).unwrap();
+        assert!(output.part1.contains("Root yells: 152"));
+    }
 }
@@ -1,5 +1,8 @@
+use crate::days::DayResult;
+use anyhow::bail;
 use itertools::Itertools;
 use std::collections::HashSet;
+use std::fmt::Write;
 
 #[derive(Debug)]
 struct Rucksack {
@@ -14,12 +17,42 @@ impl Rucksack {
         Self { compartments }
     }
 
-    fn find_duplicates(&self) -> HashSet<char> {
+    /// Splits the content evenly into `n` compartments
+    #[allow(dead_code)]
+    fn new_n_compartments(content: &str, n: usize) -> anyhow::Result<Self> {
+        let items = content.chars().collect_vec();
+        if n == 0 || !items.len().is_multiple_of(n) {
+            bail!(
+                "{} items can't be split evenly into {} compartments",
+                items.len(),
+                n
+            );
+        }
+
+        let size = items.len() / n;
+        let compartments = (0..n)
+            .map(|i| items[i * size..(i + 1) * size].iter().copied().collect())
+            .collect();
+        Ok(Self { compartments })
+    }
+
+    /// Items that are contained in every compartment
+    #[allow(dead_code)]
+    fn duplicates_across_all(&self) -> HashSet<char> {
+        self.find_duplicates()
+    }
+
+    /// Priority sum of the distinct items in each compartment
+    #[allow(dead_code)]
+    fn compartment_priorities(&self) -> anyhow::Result<Vec<usize>> {
         self.compartments
             .iter()
-            .fold(all_letters(), |set, compartment| {
-                set.intersection(compartment).copied().collect()
-            })
+            .map(|compartment| compartment.iter().copied().map(letter_priority).sum())
+            .collect()
+    }
+
+    fn find_duplicates(&self) -> HashSet<char> {
+        intersect_all(&self.compartments)
     }
 
     fn all_items(&self) -> HashSet<char> {
@@ -30,40 +63,74 @@ impl Rucksack {
             })
     }
 
-    fn find_duplicate_priority(&self) -> usize {
+    fn find_duplicate_priority(&self) -> anyhow::Result<usize> {
         let duplicates = self.find_duplicates();
         duplicates.iter().copied().map(letter_priority).sum()
     }
 }
 
-pub fn day3(content: String) -> anyhow::Result<()> {
-    println!("Day 3");
-    println!("Part 1");
+pub fn day3(content: String) -> anyhow::Result<DayResult> {
+    let mut part1 = String::new();
+    let mut part2 = String::new();
     let rucksacks = content
         .lines()
         .map(Rucksack::new_two_compartment)
         .collect_vec();
-    let priority_sum: usize = rucksacks.iter().map(|x| x.find_duplicate_priority()).sum();
-    println!("Priority sum: {}", priority_sum);
-
-    println!();
-    println!("Part 2");
-    let chunks = rucksacks.iter().chunks(3);
-    let badges = chunks.into_iter().map(find_badge).collect_vec();
-    let group_priorities: usize = badges.iter().copied().map(letter_priority).sum();
-    println!("Group priorities: {}", group_priorities);
-
-    println!();
-    Ok(())
+    let priority_sum = rucksacks
+        .iter()
+        .map(|x| x.find_duplicate_priority())
+        .sum::<anyhow::Result<usize>>()?;
+    writeln!(part1, "Priority sum: {}", priority_sum)?;
+
+    let group_priorities = group_badge_sum(&rucksacks, 3)?;
+    writeln!(part2, "Group priorities: {}", group_priorities)?;
+
+    Ok(DayResult::new(part1, Some(part2)))
+}
+
+/// Sums the badge priorities of all groups of `group_size` elfs.
+///
+/// Returns an error if the rucksacks can't be split evenly into groups of that size.
+fn group_badge_sum(rucksacks: &[Rucksack], group_size: usize) -> anyhow::Result<usize> {
+    if group_size == 0 || !rucksacks.len().is_multiple_of(group_size) {
+        bail!(
+            "{} rucksacks can't be split into groups of {}",
+            rucksacks.len(),
+            group_size
+        );
+    }
+
+    rucksacks
+        .chunks(group_size)
+        .map(|group| letter_priority(find_badge(group)?))
+        .sum()
 }
 
-/// Find the badge in a single group of elfs
-fn find_badge<'a>(elfs: impl IntoIterator<Item = &'a Rucksack>) -> char {
-    let set = elfs.into_iter().fold(all_letters(), |set, elf| {
-        set.intersection(&elf.all_items()).copied().collect()
-    });
-    assert_eq!(set.len(), 1);
-    set.into_iter().next().unwrap()
+/// Find the badge in a single group of elfs, the only item all of them carry
+fn find_badge<'a>(elfs: impl IntoIterator<Item = &'a Rucksack>) -> anyhow::Result<char> {
+    let items = elfs.into_iter().map(|x| x.all_items()).collect_vec();
+    match intersect_all(&items).into_iter().exactly_one() {
+        Ok(badge) => Ok(badge),
+        Err(candidates) => bail!(
+            "expected a single badge in the group, found {:?}",
+            candidates.sorted().collect::<String>()
+        ),
+    }
+}
+
+/// Items contained in every set, or all letters if there are no sets.
+///
+/// Only the letters of the smallest set are checked against the others, instead of narrowing
+/// down the whole alphabet one set at a time.
+fn intersect_all(sets: &[HashSet<char>]) -> HashSet<char> {
+    let Some(smallest) = sets.iter().min_by_key(|x| x.len()) else {
+        return all_letters();
+    };
+    smallest
+        .iter()
+        .copied()
+        .filter(|x| sets.iter().all(|set| set.contains(x)))
+        .collect()
 }
 
 fn all_letters() -> HashSet<char> {
@@ -73,13 +140,126 @@ fn all_letters() -> HashSet<char> {
     set
 }
 
-fn letter_priority(letter: char) -> usize {
+fn letter_priority(letter: char) -> anyhow::Result<usize> {
     if letter.is_ascii_lowercase() {
-        return letter as usize - 'a' as usize + 1;
+        return Ok(letter as usize - 'a' as usize + 1);
     }
     if letter.is_ascii_uppercase() {
-        return letter as usize - 'A' as usize + 27;
+        return Ok(letter as usize - 'A' as usize + 27);
     }
 
-    panic!("unsupported letter {}", letter);
+    bail!("unsupported letter '{}'", letter);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = r#"vJrwpWtwJgWrhcsFMMfFFhFp
+jqHRNqRjqzjGDLGLrsFMfFZSrLrFZsSL
+PmmdzqPrVvPwwTWBwg
+wMqvLMZHhHMvwLHjbvcjnnSBnvTQFn
+ttgJtRGJQctTZtZT
+CrZsJsPPZsGzwwsLwLmpwMDw"#;
+
+    #[test]
+    fn test_part_2() {
+        let rucksacks = EXAMPLE
+            .lines()
+            .map(Rucksack::new_two_compartment)
+            .collect_vec();
+        assert_eq!(group_badge_sum(&rucksacks, 3).unwrap(), 70);
+        assert!(group_badge_sum(&rucksacks, 4).is_err());
+    }
+
+    #[test]
+    fn test_find_badge_errors() {
+        let group = ["ab1c", "de1f", "gh1i"].map(Rucksack::new_two_compartment);
+        assert_eq!(
+            group_badge_sum(&group, 3).unwrap_err().to_string(),
+            "unsupported letter '1'"
+        );
+
+        let group = ["aXbY", "cXYd", "XYef"].map(Rucksack::new_two_compartment);
+        assert_eq!(
+            find_badge(&group).unwrap_err().to_string(),
+            "expected a single badge in the group, found \"XY\""
+        );
+
+        let group = ["abcd", "efgh", "ijkl"].map(Rucksack::new_two_compartment);
+        assert_eq!(
+            find_badge(&group).unwrap_err().to_string(),
+            "expected a single badge in the group, found \"\""
+        );
+    }
+
+    #[test]
+    fn test_n_compartments() {
+        let rucksack = Rucksack::new_n_compartments("abXcdXXef", 3).unwrap();
+        assert_eq!(rucksack.compartments.len(), 3);
+        assert_eq!(rucksack.duplicates_across_all(), HashSet::from(['X']));
+        assert_eq!(
+            rucksack.compartment_priorities().unwrap(),
+            vec![1 + 2 + 50, 3 + 4 + 50, 5 + 6 + 50]
+        );
+
+        assert!(Rucksack::new_n_compartments("abXcdXXefg", 3).is_err());
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let rucksack = Rucksack::new_n_compartments("abcXdefXghiX", 3).unwrap();
+        assert_eq!(rucksack.find_duplicates(), HashSet::from(['X']));
+
+        // Stray characters are reported instead of being dropped
+        let rucksack = Rucksack::new_two_compartment("a1b1");
+        assert_eq!(rucksack.find_duplicates(), HashSet::from(['1']));
+        assert_eq!(
+            rucksack.find_duplicate_priority().unwrap_err().to_string(),
+            "unsupported letter '1'"
+        );
+    }
+
+    #[test]
+    fn test_find_duplicates_many_rucksacks() {
+        // Narrowing down the whole alphabet, as it used to be done
+        let fold_duplicates = |rucksack: &Rucksack| {
+            rucksack
+                .compartments
+                .iter()
+                .fold(all_letters(), |set, compartment| {
+                    set.intersection(compartment).copied().collect()
+                })
+        };
+
+        let letters = all_letters().into_iter().sorted().collect_vec();
+        for i in 0..1000 {
+            // Compartments of different sizes and overlaps
+            let content = (0..(i % 7 + 1) * 6)
+                .map(|j| letters[(i * 31 + j * j * 7) % letters.len()])
+                .collect::<String>();
+            let rucksack = Rucksack::new_n_compartments(&content, i % 3 + 1).unwrap();
+            assert_eq!(rucksack.find_duplicates(), fold_duplicates(&rucksack));
+        }
+    }
+
+    #[test]
+    fn test_letter_priority() {
+        assert_eq!(letter_priority('a').unwrap(), 1);
+        assert_eq!(letter_priority('Z').unwrap(), 52);
+        assert_eq!(
+            letter_priority('1').unwrap_err().to_string(),
+            "unsupported letter '1'"
+        );
+        assert_eq!(
+            letter_priority(' ').unwrap_err().to_string(),
+            "unsupported letter ' '"
+        );
+    }
+
+    #[test]
+    fn test_day3_output() {
+        let output = day3(EXAMPLE.to_string()).unwrap();
+        assert!(output.part1.contains("Priority sum: 157"));
+    }
 }
@@ -1,4 +1,7 @@
+use crate::days::DayResult;
+use anyhow::Context;
 use itertools::Itertools;
+use std::fmt::Write;
 use std::ops::RangeInclusive;
 use std::str::FromStr;
 
@@ -15,10 +18,14 @@ impl Pair {
     }
 
     fn has_overlap(&self) -> bool {
-        self.first.contains(self.second.start())
-            || self.first.contains(self.second.end())
-            || self.second.contains(self.first.start())
-            || self.second.contains(self.first.end())
+        self.overlap().is_some()
+    }
+
+    /// The range covered by both elfs, if any
+    fn overlap(&self) -> Option<RangeInclusive<usize>> {
+        let start = *self.first.start().max(self.second.start());
+        let end = *self.first.end().min(self.second.end());
+        (start <= end).then_some(start..=end)
     }
 }
 
@@ -32,35 +39,94 @@ impl FromStr for Pair {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let (first, second) = s
             .split(',')
-            .map(|x| {
-                let (first, second) = x
-                    .split('-')
-                    .map(|n| n.parse().unwrap())
-                    .collect_tuple()
-                    .unwrap();
-                first..=second
-            })
             .collect_tuple()
-            .unwrap();
+            .with_context(|| format!("expected two ranges in '{}'", s))?;
+        let first = parse_range(first).with_context(|| format!("invalid pair '{}'", s))?;
+        let second = parse_range(second).with_context(|| format!("invalid pair '{}'", s))?;
 
         Ok(Self { first, second })
     }
 }
 
-pub fn day4(content: String) {
+fn parse_range(range: &str) -> anyhow::Result<RangeInclusive<usize>> {
+    let (start, end) = range
+        .split('-')
+        .collect_tuple()
+        .with_context(|| format!("expected range 'start-end', got '{}'", range))?;
+    let start = start
+        .parse()
+        .with_context(|| format!("could not parse range start '{}'", start))?;
+    let end = end
+        .parse()
+        .with_context(|| format!("could not parse range end '{}'", end))?;
+    Ok(start..=end)
+}
+
+pub fn day4(content: String) -> anyhow::Result<DayResult> {
+    let mut part1 = String::new();
+    let mut part2 = String::new();
+    let (contained_pairs, overlapping_pairs) = day4_parts(&content)?;
+
+    writeln!(part1, "Contained pairs: {}", contained_pairs)?;
+
+    writeln!(part2, "Overlapping pairs: {}", overlapping_pairs)?;
+
+    Ok(DayResult::new(part1, Some(part2)))
+}
+
+/// Returns the number of pairs where one range fully contains the other and the number of
+/// overlapping pairs
+fn day4_parts(content: &str) -> anyhow::Result<(usize, usize)> {
     let pairs = content
         .lines()
-        .map(|x| x.parse::<Pair>().unwrap())
-        .collect_vec();
-    println!("Total pairs: {}", pairs.len());
+        .map(|x| x.parse::<Pair>())
+        .collect::<anyhow::Result<Vec<Pair>>>()?;
 
-    println!();
-    println!("==== Day 4 ====");
-    println!("Part 1");
     let contained_pairs = pairs.iter().filter(|x| x.contains_other()).count();
-    println!("Contained pairs: {}", contained_pairs);
-    println!();
-    println!("Part 2");
-    let overlapping_pairs: usize = pairs.iter().filter(|x| x.has_overlap()).count();
-    println!("Overlapping pairs: {}", overlapping_pairs);
+    let overlapping_pairs = pairs.iter().filter(|x| x.has_overlap()).count();
+    Ok((contained_pairs, overlapping_pairs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = r#"2-4,6-8
+2-3,4-5
+5-7,7-9
+2-8,3-7
+6-6,4-6
+2-6,4-8"#;
+
+    #[test]
+    fn test_parts() {
+        assert_eq!(day4_parts(EXAMPLE).unwrap(), (2, 4));
+    }
+
+    #[test]
+    fn test_overlap() {
+        let pair = "2-8,3-7".parse::<Pair>().unwrap();
+        assert_eq!(pair.overlap(), Some(3..=7));
+        assert_eq!(pair.overlap().unwrap().count(), 5);
+
+        let pair = "2-4,6-8".parse::<Pair>().unwrap();
+        assert_eq!(pair.overlap(), None);
+    }
+
+    #[test]
+    fn test_invalid_pair() {
+        let error = "2-4,6-x".parse::<Pair>().unwrap_err();
+        assert_eq!(error.to_string(), "invalid pair '2-4,6-x'");
+        assert_eq!(
+            error.root_cause().to_string(),
+            "invalid digit found in string"
+        );
+        assert!("2-4".parse::<Pair>().is_err());
+    }
+
+    #[test]
+    fn test_day4_output() {
+        let output = day4(EXAMPLE.to_string()).unwrap();
+        assert!(output.part1.contains("Contained pairs: 2"));
+    }
 }
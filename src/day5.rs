@@ -1,5 +1,7 @@
+use crate::days::DayResult;
+use anyhow::{bail, Context};
 use itertools::Itertools;
-use std::iter;
+use std::fmt::Write;
 use std::str::FromStr;
 
 #[derive(Debug, Clone)]
@@ -8,12 +10,34 @@ struct SupplyStacks {
 }
 
 impl SupplyStacks {
-    fn pop(&mut self, index: usize) -> Option<char> {
-        self.stacks.get_mut(index).unwrap().pop()
+    fn pop(&mut self, index: usize) -> anyhow::Result<char> {
+        let stack_count = self.stacks.len();
+        self.stacks
+            .get_mut(index)
+            .with_context(|| {
+                format!(
+                    "move from stack {} but only {} stacks exist",
+                    index + 1,
+                    stack_count
+                )
+            })?
+            .pop()
+            .with_context(|| format!("move from empty stack {}", index + 1))
     }
 
-    fn push(&mut self, index: usize, cargo: char) {
-        self.stacks.get_mut(index).unwrap().push(cargo);
+    fn push(&mut self, index: usize, cargo: char) -> anyhow::Result<()> {
+        let stack_count = self.stacks.len();
+        self.stacks
+            .get_mut(index)
+            .with_context(|| {
+                format!(
+                    "move to stack {} but only {} stacks exist",
+                    index + 1,
+                    stack_count
+                )
+            })?
+            .push(cargo);
+        Ok(())
     }
 
     fn top(&self) -> String {
@@ -26,21 +50,29 @@ impl FromStr for SupplyStacks {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut lines = s.lines().collect_vec();
-        let crate_positions = lines
-            .pop()
-            .unwrap()
-            .chars()
-            .enumerate()
-            .filter(|(_, c)| !c.is_whitespace())
-            .map(|(x, _)| x)
-            .collect_vec();
-
-        let mut stacks = iter::repeat(vec![])
-            .take(crate_positions.len())
-            .collect_vec();
+        let labels = lines.pop().context("missing stack label row")?;
+        // Only count the labels, they may be wider than a single character
+        let stack_count = labels.split_whitespace().count();
+        if stack_count == 0 {
+            bail!("no stack labels found in '{}'", labels);
+        }
+
+        let mut stacks = vec![vec![]; stack_count];
         while let Some(level) = lines.pop() {
-            for (&index, stack) in crate_positions.iter().zip(stacks.iter_mut()) {
-                let c = level.chars().nth(index).unwrap();
+            let level = level.chars().collect_vec();
+            if level.len() > stack_count * 4 {
+                bail!(
+                    "crate row '{}' is wider than {} stacks",
+                    level.iter().collect::<String>(),
+                    stack_count
+                );
+            }
+
+            // Each crate is drawn as `[X] `, short rows simply have no crates on the right
+            for (index, stack) in stacks.iter_mut().enumerate() {
+                let Some(&c) = level.get(index * 4 + 1) else {
+                    break;
+                };
                 if !c.is_whitespace() {
                     stack.push(c);
                 }
@@ -57,24 +89,31 @@ struct Instruction {
     to: usize,
 }
 
-impl Instruction {
-    pub fn execute_single_crate(&self, stack: &mut SupplyStacks) {
-        for _ in 0..self.count {
-            let c = stack.pop(self.from).unwrap();
-            stack.push(self.to, c);
-        }
-    }
+/// How a crane moves multiple crates
+#[derive(Debug, Copy, Clone)]
+enum CrateMover {
+    /// CrateMover 9000, reverses the order of the moved crates
+    OneAtATime,
+    /// CrateMover 9001, keeps the order of the moved crates
+    AllAtOnce,
+}
 
-    pub fn execute_multi_crate(&self, stack: &mut SupplyStacks) {
+impl Instruction {
+    pub fn execute(&self, stack: &mut SupplyStacks, mode: CrateMover) -> anyhow::Result<()> {
         let mut temp = vec![];
         for _ in 0..self.count {
-            let c = stack.pop(self.from).unwrap();
+            let c = stack.pop(self.from)?;
             temp.push(c);
         }
 
-        while let Some(c) = temp.pop() {
-            stack.push(self.to, c);
+        if let CrateMover::AllAtOnce = mode {
+            temp.reverse();
         }
+
+        for c in temp {
+            stack.push(self.to, c)?;
+        }
+        Ok(())
     }
 }
 
@@ -82,48 +121,163 @@ impl FromStr for Instruction {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut line = s.split_whitespace();
-
-        // Move
-        let _ = line.next().unwrap();
-        let count = line.next().unwrap().parse::<usize>()?;
+        let mut words = s.split_whitespace();
+        let count = parse_argument(&mut words, "move")
+            .with_context(|| format!("invalid instruction '{}'", s))?;
+        let from = parse_argument(&mut words, "from")
+            .with_context(|| format!("invalid instruction '{}'", s))?;
+        let to = parse_argument(&mut words, "to")
+            .with_context(|| format!("invalid instruction '{}'", s))?;
 
-        // From
-        let _ = line.next().unwrap();
-        let from = line.next().unwrap().parse::<usize>()?;
-        let from = from - 1;
+        if words.next().is_some() {
+            bail!("unexpected data after instruction '{}'", s);
+        }
+        if from == 0 || to == 0 {
+            bail!("stacks are numbered from 1 in instruction '{}'", s);
+        }
 
-        // To
-        let _ = line.next().unwrap();
-        let to = line.next().unwrap().parse::<usize>()?;
-        let to = to - 1;
+        Ok(Instruction {
+            count,
+            from: from - 1,
+            to: to - 1,
+        })
+    }
+}
 
-        Ok(Instruction { count, from, to })
+/// Parses a `keyword number` pair
+fn parse_argument<'a>(
+    words: &mut impl Iterator<Item = &'a str>,
+    keyword: &str,
+) -> anyhow::Result<usize> {
+    let word = words
+        .next()
+        .with_context(|| format!("missing '{}'", keyword))?;
+    if word != keyword {
+        bail!("expected '{}', got '{}'", keyword, word);
     }
+    let value = words
+        .next()
+        .with_context(|| format!("missing value after '{}'", keyword))?;
+    value
+        .parse::<usize>()
+        .with_context(|| format!("could not parse '{}' value '{}'", keyword, value))
+}
+
+pub fn day5(content: String) -> anyhow::Result<DayResult> {
+    let (single_crate_top, multi_crate_top) = day5_parts(&content)?;
+
+    let mut part1 = String::new();
+    let mut part2 = String::new();
+    writeln!(part1, "Top: {}", single_crate_top)?;
+
+    writeln!(part2, "Top: {}", multi_crate_top)?;
+
+    Ok(DayResult::new(part1, Some(part2)))
 }
 
-pub fn day5(content: String) {
-    println!();
-    println!("==== Day 5 ====");
-    let (stacks, instructions) = content.split("\n\n").collect_tuple().unwrap();
+/// Returns the top crates after using the single crate and the multi crate mover
+pub fn day5_parts(content: &str) -> anyhow::Result<(String, String)> {
+    let (stacks, instructions) = content
+        .split("\n\n")
+        .collect_tuple()
+        .context("expected stacks and instructions separated by a blank line")?;
 
-    let original_stacks = stacks.parse::<SupplyStacks>().unwrap();
+    let original_stacks = stacks.parse::<SupplyStacks>()?;
     let instructions = instructions
         .lines()
-        .map(|x| x.parse::<Instruction>().unwrap())
-        .collect_vec();
+        .map(|x| x.parse::<Instruction>())
+        .collect::<anyhow::Result<Vec<Instruction>>>()?;
+
+    let [single_crate_top, multi_crate_top] =
+        [CrateMover::OneAtATime, CrateMover::AllAtOnce].map(|mode| {
+            let mut stacks = original_stacks.clone();
+            for instruction in &instructions {
+                instruction
+                    .execute(&mut stacks, mode)
+                    .with_context(|| format!("could not execute {:?}", instruction))?;
+            }
+            anyhow::Ok(stacks.top())
+        });
+
+    Ok((single_crate_top?, multi_crate_top?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::load_example;
+
+    #[test]
+    fn test_parts() {
+        let (part_1, part_2) = day5_parts(&load_example(5)).unwrap();
+        assert_eq!(part_1, "CMZ");
+        assert_eq!(part_2, "MCD");
+    }
+
+    #[test]
+    fn test_crate_mover() {
+        let original_stacks = "[A]\n[B]\n[C]\n 1   2".parse::<SupplyStacks>().unwrap();
+        let instruction = "move 3 from 1 to 2".parse::<Instruction>().unwrap();
+
+        let mut stacks = original_stacks.clone();
+        instruction
+            .execute(&mut stacks, CrateMover::OneAtATime)
+            .unwrap();
+        assert_eq!(stacks.stacks[1], vec!['A', 'B', 'C']);
+
+        let mut stacks = original_stacks;
+        instruction
+            .execute(&mut stacks, CrateMover::AllAtOnce)
+            .unwrap();
+        assert_eq!(stacks.stacks[1], vec!['C', 'B', 'A']);
+    }
+
+    #[test]
+    fn test_out_of_range_move() {
+        let mut stacks = "[A]\n[B] [C] [D]\n 1   2   3"
+            .parse::<SupplyStacks>()
+            .unwrap();
+        let instruction = "move 1 from 9 to 1".parse::<Instruction>().unwrap();
+        let error = instruction
+            .execute(&mut stacks, CrateMover::OneAtATime)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "move from stack 9 but only 3 stacks exist"
+        );
+
+        let instruction = "move 2 from 2 to 1".parse::<Instruction>().unwrap();
+        let error = instruction
+            .execute(&mut stacks, CrateMover::OneAtATime)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "move from empty stack 2");
+    }
+
+    #[test]
+    fn test_multi_character_labels() {
+        let stacks = r#"[A]                                         [K]
+[B]                                     [J] [L]
+ 1   2   3   4   5   6   7   8   9  10  11  12"#
+            .parse::<SupplyStacks>()
+            .unwrap();
+        assert_eq!(stacks.stacks.len(), 12);
+        assert_eq!(stacks.top(), "AJK");
+    }
 
-    println!("Part 1");
-    let mut stacks = original_stacks.clone();
-    for instruction in &instructions {
-        instruction.execute_single_crate(&mut stacks);
+    #[test]
+    fn test_invalid_instruction() {
+        let error = "move 1 from x to 2".parse::<Instruction>().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid instruction 'move 1 from x to 2'"
+        );
+        assert!("move 1 to 2".parse::<Instruction>().is_err());
+        assert!("move 1 from 0 to 2".parse::<Instruction>().is_err());
     }
-    println!("Top: {}", stacks.top());
 
-    println!("Part 2");
-    let mut stacks = original_stacks;
-    for instruction in &instructions {
-        instruction.execute_multi_crate(&mut stacks);
+    #[test]
+    fn test_day5_output() {
+        let output = day5(load_example(5)).unwrap();
+        assert!(output.part1.contains("Top: CMZ"));
     }
-    println!("Top: {}", stacks.top());
 }
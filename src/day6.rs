@@ -1,10 +1,14 @@
+use crate::days::DayResult;
 use itertools::Itertools;
+use std::collections::HashMap;
+use std::fmt::Write;
 
 fn is_distinct(markers: &[char]) -> bool {
     markers.iter().duplicates().next().is_none()
 }
 
-fn find_start_of_packet(content: &str, length: usize) -> (String, usize) {
+#[allow(dead_code)]
+fn find_start_of_packet_naive(content: &str, length: usize) -> (String, usize) {
     let content = content.chars().collect_vec();
     let (index, group) = content
         .windows(length)
@@ -15,22 +19,71 @@ fn find_start_of_packet(content: &str, length: usize) -> (String, usize) {
     (group.iter().collect(), index + length)
 }
 
-pub fn day6(content: String) {
-    println!();
-    println!("==== Day 6 ====");
+/// Finds the first window of `length` distinct characters, returning the marker and the index
+/// after it.
+///
+/// Keeps a running count per character, so each character is only looked at twice.
+fn find_start_of_packet(content: &str, length: usize) -> (String, usize) {
+    let content = content.chars().collect_vec();
+    let mut counts = HashMap::new();
+    let mut duplicates = 0;
+
+    for (index, &c) in content.iter().enumerate() {
+        let count = counts.entry(c).or_insert(0);
+        *count += 1;
+        if *count == 2 {
+            duplicates += 1;
+        }
+
+        if index >= length {
+            let count = counts.get_mut(&content[index - length]).unwrap();
+            *count -= 1;
+            if *count == 1 {
+                duplicates -= 1;
+            }
+        }
+
+        if index + 1 >= length && duplicates == 0 {
+            let start = index + 1 - length;
+            return (content[start..=index].iter().collect(), index + 1);
+        }
+    }
+
+    panic!("no marker found");
+}
+
+/// Returns the index after every window of `length` distinct characters, in order
+#[allow(dead_code)]
+fn all_start_markers(content: &str, length: usize) -> Vec<usize> {
+    let content = content.chars().collect_vec();
+    content
+        .windows(length)
+        .enumerate()
+        .filter(|(_, markers)| is_distinct(markers))
+        .map(|(index, _)| index + length)
+        .collect()
+}
+
+pub fn day6(content: String) -> anyhow::Result<DayResult> {
+    let mut part1 = String::new();
+    let mut part2 = String::new();
 
-    println!("Part 1");
     let (marker, end_of_marker) = find_start_of_packet(&content, 4);
-    println!("Marker: {:?} Packet start: {}", marker, end_of_marker);
+    writeln!(
+        part1,
+        "Marker: {:?} Packet start: {}",
+        marker, end_of_marker
+    )?;
 
-    println!("Part 2");
     let (_, start_of_message) = find_start_of_packet(&content, 14);
-    println!("Start of message: {}", start_of_message);
+    writeln!(part2, "Start of message: {}", start_of_message)?;
+
+    Ok(DayResult::new(part1, Some(part2)))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::day6::find_start_of_packet;
+    use super::*;
 
     #[test]
     fn test_find_marker() {
@@ -63,4 +116,40 @@ mod tests {
             assert_eq!(first_repeated, target_repeated);
         }
     }
+
+    #[test]
+    fn test_matches_naive() {
+        let values = [
+            "mjqjpqmgbljsphdztnvjfqwrcgsmlb",
+            "bvwbjplbgvbhsrlpgdmjqwftvncz",
+            "nppdvjthqldpwncqszvftbrmjlhg",
+            "nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg",
+            "zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw",
+        ];
+
+        for value in values {
+            for length in [4, 14] {
+                assert_eq!(
+                    find_start_of_packet(value, length),
+                    find_start_of_packet_naive(value, length)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_all_start_markers() {
+        let markers = all_start_markers("mjqjpqmgbljsphdztnvjfqwrcgsmlb", 4);
+        assert_eq!(markers.first(), Some(&7));
+        assert_eq!(markers.len(), 24);
+        assert_eq!(&markers[..3], &[7, 8, 9]);
+
+        assert_eq!(all_start_markers("aabb", 2), vec![3]);
+    }
+
+    #[test]
+    fn test_day6_output() {
+        let output = day6("mjqjpqmgbljsphdztnvjfqwrcgsmlb".to_string()).unwrap();
+        assert!(output.part1.contains("Packet start: 7"));
+    }
 }
@@ -1,188 +1,233 @@
+use crate::days::DayResult;
 use itertools::Itertools;
 use std::collections::HashMap;
+use std::fmt::Write;
 
+/// Filesystem where every node refers to its parent by index, so neither building it nor summing
+/// sizes recurses, however deeply the directories are nested
 #[derive(Debug)]
-enum File<'a> {
-    File { size: usize },
-    Directory { files: HashMap<&'a str, File<'a>> },
+struct Filesystem<'a> {
+    nodes: Vec<Node<'a>>,
 }
 
-impl<'a> File<'a> {
-    fn new_dir() -> Self {
-        Self::Directory {
-            files: Default::default(),
+#[derive(Debug)]
+struct Node<'a> {
+    parent: Option<usize>,
+    /// Own size of a file, 0 for directories
+    size: usize,
+    /// `None` for files
+    children: Option<HashMap<&'a str, usize>>,
+}
+
+impl<'a> Node<'a> {
+    fn new_dir(parent: usize) -> Self {
+        Self {
+            parent: Some(parent),
+            size: 0,
+            children: Some(HashMap::new()),
         }
     }
 
-    fn new_file(size: usize) -> Self {
-        Self::File { size }
+    fn new_file(parent: usize, size: usize) -> Self {
+        Self {
+            parent: Some(parent),
+            size,
+            children: None,
+        }
     }
+}
+
+impl<'a> Filesystem<'a> {
+    const ROOT: usize = 0;
 
-    fn extract_filesystem(&mut self, command_stack: &mut Vec<&'a str>) {
-        let Self::Directory {files} = self else {
-            panic!();
+    /// Replays the terminal output, which has to start in the root directory
+    fn from_terminal(content: &'a str) -> Self {
+        let mut filesystem = Self {
+            nodes: vec![Node {
+                parent: None,
+                size: 0,
+                children: Some(HashMap::new()),
+            }],
         };
 
-        while let Some(command) = command_stack.pop() {
-            match &command[0..4] {
-                "$ cd" => {
-                    let name = &command[5..];
-
-                    if name == ".." {
-                        return;
-                    }
-
-                    let sub_dir = files.get_mut(name).unwrap();
-                    sub_dir.extract_filesystem(command_stack);
-                }
-                "$ ls" => {
-                    while let Some(value) = command_stack.last() {
-                        if value.starts_with('$') {
-                            break;
-                        }
-
-                        let file = command_stack.pop().unwrap();
-
-                        if file.starts_with("dir") {
-                            let name = &file[4..];
-                            let dir = Self::new_dir();
-                            files.insert(name, dir);
-                        } else {
-                            let (size, name): (&str, &str) =
-                                file.split_whitespace().collect_tuple().unwrap();
-                            let size = size
-                                .parse::<usize>()
-                                .unwrap_or_else(|_| panic!("{} could not be parsed to int", size));
-
-                            files.insert(name, File::new_file(size));
-                        }
-                    }
-                }
-                command => panic!("unknown command {}", command),
+        let mut current = Self::ROOT;
+        for line in content.lines() {
+            if let Some(name) = line.strip_prefix("$ cd ") {
+                current = match name {
+                    "/" => Self::ROOT,
+                    // Moving up from the root keeps us at the root
+                    ".." => filesystem.nodes[current].parent.unwrap_or(Self::ROOT),
+                    name => *filesystem
+                        .children(current)
+                        .get(name)
+                        .unwrap_or_else(|| panic!("unknown directory {}", name)),
+                };
+            } else if line == "$ ls" {
+                // The listing follows on the next lines
+            } else if let Some(name) = line.strip_prefix("dir ") {
+                filesystem.add(current, name, Node::new_dir(current));
+            } else if line.starts_with('$') {
+                panic!("unknown command {}", line);
+            } else {
+                let (size, name) = line.split_whitespace().collect_tuple().unwrap();
+                let size = size
+                    .parse::<usize>()
+                    .unwrap_or_else(|_| panic!("{} could not be parsed to int", size));
+                filesystem.add(current, name, Node::new_file(current, size));
             }
         }
+        filesystem
     }
 
-    fn size(&self) -> usize {
-        match self {
-            File::File { size } => *size,
-            File::Directory { files } => files.iter().map(|(_, x)| x.size()).sum(),
+    fn children(&self, index: usize) -> &HashMap<&'a str, usize> {
+        self.nodes[index]
+            .children
+            .as_ref()
+            .unwrap_or_else(|| panic!("node {} is not a directory", index))
+    }
+
+    /// Adds `node` to the directory at `parent`, unless it was already listed before
+    fn add(&mut self, parent: usize, name: &'a str, node: Node<'a>) {
+        let index = self.nodes.len();
+        let children = self.nodes[parent].children.as_mut().unwrap();
+        if !children.contains_key(name) {
+            children.insert(name, index);
+            self.nodes.push(node);
         }
     }
 
-    fn visit_dirs(&self, name: &str, func: &mut dyn FnMut(&str, &HashMap<&str, File>)) {
-        match self {
-            File::File { .. } => {
-                // Do nothing
-            }
-            File::Directory { files } => {
-                func(name, files);
+    /// Total size of every node, including everything below it.
+    ///
+    /// Nodes are only ever added after their parent, so going backwards every node is complete
+    /// before it is added to its parent. This visits every node once instead of once per
+    /// directory above it.
+    fn node_sizes(&self) -> Vec<usize> {
+        let mut totals = self.nodes.iter().map(|x| x.size).collect_vec();
+        for (index, node) in self.nodes.iter().enumerate().skip(1).rev() {
+            totals[node.parent.unwrap()] += totals[index];
+        }
+        totals
+    }
 
-                for (name, file) in files {
-                    file.visit_dirs(name, func);
-                }
+    /// Total size of the root directory
+    #[allow(dead_code)]
+    fn total_size(&self) -> usize {
+        self.node_sizes()[Self::ROOT]
+    }
+
+    /// Total size of every directory, the root first
+    fn all_dir_sizes(&self) -> Vec<usize> {
+        self.nodes
+            .iter()
+            .zip(self.node_sizes())
+            .filter(|(node, _)| node.children.is_some())
+            .map(|(_, total)| total)
+            .collect()
+    }
+
+    /// Total size of every directory, keyed by its full path, e.g. `/a/e`
+    #[allow(dead_code)]
+    fn dir_sizes(&self) -> HashMap<String, usize> {
+        let totals = self.node_sizes();
+
+        // Parents come before their children, so their path is always known already
+        let mut paths = vec![String::new(); self.nodes.len()];
+        paths[Self::ROOT] = "/".to_owned();
+        let mut sizes = HashMap::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            let Some(children) = &node.children else {
+                continue;
+            };
+            for (name, &child) in children {
+                paths[child] = format!("{}/{}", paths[index].trim_end_matches('/'), name);
             }
+            sizes.insert(std::mem::take(&mut paths[index]), totals[index]);
         }
+        sizes
     }
 }
 
-fn sum_dirs_with_max_size(file: &File, name: &str, max_size: usize) -> usize {
-    let mut sum = 0;
-    file.visit_dirs(name, &mut |_, dir| {
-        let size: usize = dir.iter().map(|(_, file)| file.size()).sum();
-        if size <= max_size {
-            sum += size;
-        }
-    });
-    sum
+fn sum_dirs_with_max_size(dir_sizes: &[usize], max_size: usize) -> usize {
+    dir_sizes.iter().filter(|&&size| size <= max_size).sum()
 }
 
-fn smallest_dir_with_enough_space(file: &File, name: &str, min_size: usize) -> usize {
-    let mut list = vec![];
-    file.visit_dirs(name, &mut |_, dir| {
-        // code duplication...
-        let size: usize = dir.iter().map(|(_, file)| file.size()).sum();
+fn smallest_dir_with_enough_space(dir_sizes: &[usize], min_size: usize) -> usize {
+    *dir_sizes
+        .iter()
+        .filter(|&&size| size >= min_size)
+        .min()
+        .unwrap()
+}
 
-        if size >= min_size {
-            list.push(size);
-        }
-    });
+/// Size of the smallest directory that has to be deleted to get `needed` free space on a disk
+/// of size `disk_size`. Returns 0 if there is already enough free space.
+fn smallest_dir_to_free_space(filesystem: &Filesystem, disk_size: usize, needed: usize) -> usize {
+    let dir_sizes = filesystem.all_dir_sizes();
+    // The root holds everything else
+    let current_free_space = disk_size.saturating_sub(dir_sizes[0]);
+    if current_free_space >= needed {
+        return 0;
+    }
 
-    *list.iter().min().unwrap()
+    let min_delete_size = needed - current_free_space;
+    smallest_dir_with_enough_space(&dir_sizes, min_delete_size)
 }
 
-pub fn day7(content: String) {
-    println!();
-    println!("==== Day 7 ====");
+pub fn day7(content: String) -> anyhow::Result<DayResult> {
+    let mut part1 = String::new();
+    let mut part2 = String::new();
 
-    let mut command_stack = content.lines().rev().collect_vec();
-    let enter_root = command_stack.pop().unwrap();
+    let enter_root = content.lines().next().unwrap();
     assert_eq!(enter_root, "$ cd /");
 
-    let mut root = File::new_dir();
-    root.extract_filesystem(&mut command_stack);
-    let root = root;
+    let filesystem = Filesystem::from_terminal(&content);
+    let file_size_sum = sum_dirs_with_max_size(&filesystem.all_dir_sizes(), 100000);
+    writeln!(part1, "Sum of Dir sizes below 10000: {}", file_size_sum)?;
 
-    println!("Part 1");
-    let file_size_sum = sum_dirs_with_max_size(&root, "/", 100000);
-    println!("Sum of Dir sizes below 10000: {}", file_size_sum);
+    let smallest_file_to_delete = smallest_dir_to_free_space(&filesystem, 70000000, 30000000);
+    writeln!(part2, "Deleted file size: {}", smallest_file_to_delete)?;
 
-    println!("Part 2");
-    let current_free_space = 70000000 - root.size();
-    let min_delete_size = 30000000 - current_free_space;
-    let smallest_file_to_delete = smallest_dir_with_enough_space(&root, "/", min_delete_size);
-    println!("Deleted file size: {}", smallest_file_to_delete);
+    Ok(DayResult::new(part1, Some(part2)))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::load_example;
 
     #[test]
     fn test_example_part_1() {
-        let example = r#"$ cd /
-$ ls
-dir a
-14848514 b.txt
-8504156 c.dat
-dir d
-$ cd a
-$ ls
-dir e
-29116 f
-2557 g
-62596 h.lst
-$ cd e
-$ ls
-584 i
-$ cd ..
-$ cd ..
-$ cd d
-$ ls
-4060174 j
-8033020 d.log
-5626152 d.ext
-7214296 k"#;
-
-        let mut command_stack = example.lines().rev().collect_vec();
-        let _ = command_stack.pop().unwrap();
+        let example = load_example(7);
+        let filesystem = Filesystem::from_terminal(&example);
 
-        let mut root = File::new_dir();
-        root.extract_filesystem(&mut command_stack);
-        let root = root;
-
-        let file_size_sum = sum_dirs_with_max_size(&root, "/", 100000);
+        let file_size_sum = sum_dirs_with_max_size(&filesystem.all_dir_sizes(), 100000);
         assert_eq!(file_size_sum, 95437);
     }
 
     #[test]
     fn test_example_part_2() {
+        let example = load_example(7);
+        let filesystem = Filesystem::from_terminal(&example);
+
+        let smallest_file_to_delete = smallest_dir_to_free_space(&filesystem, 70000000, 30000000);
+        assert_eq!(smallest_file_to_delete, 24933642);
+
+        // Enough space is left already
+        assert_eq!(
+            smallest_dir_to_free_space(&filesystem, 100000000, 30000000),
+            0
+        );
+    }
+
+    #[test]
+    fn test_cd_root_mid_stream() {
         let example = r#"$ cd /
 $ ls
 dir a
 14848514 b.txt
 8504156 c.dat
 dir d
+$ cd ..
 $ cd a
 $ ls
 dir e
@@ -192,8 +237,7 @@ dir e
 $ cd e
 $ ls
 584 i
-$ cd ..
-$ cd ..
+$ cd /
 $ cd d
 $ ls
 4060174 j
@@ -201,16 +245,121 @@ $ ls
 5626152 d.ext
 7214296 k"#;
 
-        let mut command_stack = example.lines().rev().collect_vec();
-        let _ = command_stack.pop().unwrap();
+        let filesystem = Filesystem::from_terminal(example);
+        assert_eq!(filesystem.total_size(), 48381165);
+        let file_size_sum = sum_dirs_with_max_size(&filesystem.all_dir_sizes(), 100000);
+        assert_eq!(file_size_sum, 95437);
+    }
+
+    #[test]
+    fn test_dir_sizes() {
+        let example = load_example(7);
+        let filesystem = Filesystem::from_terminal(&example);
 
-        let mut root = File::new_dir();
-        root.extract_filesystem(&mut command_stack);
-        let root = root;
+        let sizes = filesystem.dir_sizes();
+        assert_eq!(sizes.len(), 4);
+        assert_eq!(sizes["/"], 48381165);
+        assert_eq!(sizes["/a"], 94853);
+        assert_eq!(sizes["/a/e"], 584);
+        assert_eq!(sizes["/d"], 24933642);
+    }
 
-        let current_free_space = 70000000 - root.size();
-        let min_delete_size = 30000000 - current_free_space;
-        let smallest_file_to_delete = smallest_dir_with_enough_space(&root, "/", min_delete_size);
-        assert_eq!(smallest_file_to_delete, 24933642);
+    #[test]
+    fn test_dir_sizes_same_names() {
+        let example = "$ cd /\n$ ls\ndir a\ndir b\n$ cd a\n$ ls\ndir a\n1 f\n$ cd a\n$ ls\n2 f\n$ cd /\n$ cd b\n$ ls\ndir a\n$ cd a\n$ ls\n4 f";
+
+        let sizes = Filesystem::from_terminal(example).dir_sizes();
+        assert_eq!(sizes.len(), 5);
+        assert_eq!(sizes["/"], 7);
+        assert_eq!(sizes["/a"], 3);
+        assert_eq!(sizes["/a/a"], 2);
+        assert_eq!(sizes["/b"], 4);
+        assert_eq!(sizes["/b/a"], 4);
+    }
+
+    /// Recomputes the size of every directory from scratch, by summing every file below it
+    fn naive_dir_sizes(filesystem: &Filesystem) -> Vec<usize> {
+        let is_below = |mut index: usize, dir: usize| loop {
+            if index == dir {
+                return true;
+            }
+            match filesystem.nodes[index].parent {
+                Some(parent) => index = parent,
+                None => return false,
+            }
+        };
+
+        (0..filesystem.nodes.len())
+            .filter(|&dir| filesystem.nodes[dir].children.is_some())
+            .map(|dir| {
+                (0..filesystem.nodes.len())
+                    .filter(|&index| is_below(index, dir))
+                    .map(|index| filesystem.nodes[index].size)
+                    .sum()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        // Every directory holds a file and the next directory, 500 levels deep
+        let mut example = String::from("$ cd /\n");
+        for depth in 1..=500 {
+            writeln!(example, "$ ls\ndir d\n{} f\n$ cd d", depth).unwrap();
+        }
+        example.push_str("$ ls\n1000 f");
+
+        let filesystem = Filesystem::from_terminal(&example);
+        let sizes = filesystem.all_dir_sizes();
+        let naive = naive_dir_sizes(&filesystem);
+        assert_eq!(sizes.len(), 501);
+        assert_eq!(sizes, naive);
+        assert_eq!(sizes[0], 500 * 501 / 2 + 1000);
+
+        let max_size = 100000;
+        let naive_sum: usize = naive.iter().filter(|&&x| x <= max_size).sum();
+        assert_eq!(sum_dirs_with_max_size(&sizes, max_size), naive_sum);
+        let naive_smallest = naive.iter().filter(|&&x| x >= max_size).min();
+        assert_eq!(
+            Some(smallest_dir_with_enough_space(&sizes, max_size)),
+            naive_smallest.copied()
+        );
+    }
+
+    #[test]
+    fn test_deep_chain() {
+        // Deep enough that a recursive walk would need thousands of stack frames
+        let mut example = String::from("$ cd /\n");
+        for _ in 0..5000 {
+            example.push_str("$ ls\ndir d\n1 f\n$ cd d\n");
+        }
+        example.push_str("$ cd ..\n$ cd ..\n$ ls\ndir d\n1 f");
+
+        let filesystem = Filesystem::from_terminal(&example);
+        let sizes = filesystem.all_dir_sizes();
+        assert_eq!(sizes.len(), 5001);
+        let output = day7(example.clone()).unwrap();
+        assert!(output
+            .part1
+            .contains("Sum of Dir sizes below 10000: 12502500"));
+        assert_eq!(filesystem.total_size(), 5000);
+        // The innermost directory is empty, every other one holds a file and the next one
+        assert_eq!(
+            sizes.iter().copied().sorted().collect_vec(),
+            (0..=5000).collect_vec()
+        );
+        assert_eq!(sum_dirs_with_max_size(&sizes, 10), 55);
+        assert_eq!(smallest_dir_to_free_space(&filesystem, 5010, 20), 10);
+        assert_eq!(filesystem.dir_sizes()[&"/d".repeat(5000)], 0);
+    }
+
+    #[test]
+    fn test_day7_output() {
+        let output = day7(load_example(7)).unwrap();
+        assert!(output.part1.contains("Sum of Dir sizes below 10000: 95437"));
+        assert!(output
+            .part2
+            .unwrap()
+            .contains("Deleted file size: 24933642"));
     }
 }
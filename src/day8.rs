@@ -1,6 +1,11 @@
+use crate::days::DayResult;
+use crate::utils::Grid;
+use anyhow::{bail, Context};
 use std::fmt::{Display, Formatter, Write};
 use std::str::FromStr;
 
+type Point2 = na::Point2<usize>;
+
 #[derive(Debug)]
 struct VisibilityMap {
     visible: Vec<bool>,
@@ -35,45 +40,55 @@ enum LookDir {
 }
 
 struct TreeGrid {
-    trees: Vec<u8>,
-    width: usize,
-    height: usize,
+    trees: Grid<u8>,
 }
 
 impl TreeGrid {
     fn tree(&self, x: usize, y: usize) -> Option<u8> {
-        self.trees.get(self.index(x, y)?).copied()
+        self.trees.get(&Point2::new(x, y)).copied()
+    }
+
+    /// Heights of the trees in row `y`, from left to right
+    #[allow(dead_code)]
+    fn row(&self, y: usize) -> Option<&[u8]> {
+        let width = self.trees.width();
+        self.trees.cells().get(y * width..(y + 1) * width)
     }
 
-    fn rows(&self) -> LinesIter {
+    /// Heights of the trees in column `x`, from top to bottom. Empty if `x` is out of bounds.
+    #[allow(dead_code)]
+    fn column(&self, x: usize) -> Vec<u8> {
+        (0..self.trees.height())
+            .map_while(|y| self.tree(x, y))
+            .collect()
+    }
+
+    fn rows(&self) -> LinesIter<'_> {
         LinesIter {
             grid: self,
             pos: (0, 0),
-            pos_back: (self.width - 1, 0),
+            pos_back: (self.trees.width() - 1, 0),
             dir: (0, 1),
             line_dir: (1, 0),
         }
     }
 
-    fn columns(&self) -> LinesIter {
+    fn columns(&self) -> LinesIter<'_> {
         LinesIter {
             grid: self,
             pos: (0, 0),
-            pos_back: (0, self.height - 1),
+            pos_back: (0, self.trees.height() - 1),
             dir: (1, 0),
             line_dir: (0, 1),
         }
     }
 
     fn index(&self, x: usize, y: usize) -> Option<usize> {
-        if x >= self.width || y >= self.height {
-            return None;
-        }
-        Some(x + y * self.width)
+        self.trees.index(&Point2::new(x, y))
     }
 
     fn visibility(&self) -> VisibilityMap {
-        let mut visible = vec![false; self.trees.len()];
+        let mut visible = vec![false; self.trees.cells().len()];
 
         for line in self.rows() {
             self.mark_visible(&mut visible, line.clone());
@@ -87,16 +102,71 @@ impl TreeGrid {
 
         VisibilityMap {
             visible,
-            width: self.width,
-            _height: self.height,
+            width: self.trees.width(),
+            _height: self.trees.height(),
+        }
+    }
+
+    /// Like `visibility`, but trees can also be seen along the four diagonals
+    #[allow(dead_code)]
+    fn visibility_with_diagonals(&self) -> VisibilityMap {
+        let mut visibility = self.visibility();
+
+        for line in self.diagonals() {
+            self.mark_visible(&mut visibility.visible, line.clone());
+            self.mark_visible(&mut visibility.visible, line.rev());
+        }
+
+        visibility
+    }
+
+    fn diagonals(&self) -> Vec<LineIter<'_>> {
+        let mut lines = vec![];
+
+        // Down-right diagonals start on the top row or the left column
+        let starts = (0..self.trees.width())
+            .map(|x| (x, 0))
+            .chain((1..self.trees.height()).map(|y| (0, y)));
+        for pos in starts {
+            let length = (self.trees.width() - pos.0).min(self.trees.height() - pos.1);
+            lines.push(LineIter {
+                grid: self,
+                pos,
+                pos_back: (pos.0 + length - 1, pos.1 + length - 1),
+                dir: (1, 1),
+                is_finished: false,
+            });
         }
+
+        // Up-right diagonals start on the bottom row or the left column
+        let starts = (0..self.trees.width())
+            .map(|x| (x, self.trees.height() - 1))
+            .chain((0..self.trees.height() - 1).map(|y| (0, y)));
+        for pos in starts {
+            let length = (self.trees.width() - pos.0).min(pos.1 + 1);
+            lines.push(LineIter {
+                grid: self,
+                pos,
+                pos_back: (pos.0 + length - 1, pos.1 + 1 - length),
+                dir: (1, -1),
+                is_finished: false,
+            });
+        }
+
+        lines
     }
 
     fn max_visibility_score(&self) -> usize {
+        let (_, score) = self.best_scenic_position();
+        score
+    }
+
+    /// Returns the position of the tree with the highest scenic score, and its score
+    fn best_scenic_position(&self) -> ((usize, usize), usize) {
         self.rows()
             .flatten()
-            .map(|(_, pos)| self.visibility_score(pos))
-            .max()
+            .map(|(_, pos)| (pos, self.visibility_score(pos)))
+            .max_by_key(|(_, score)| *score)
             .unwrap()
     }
 
@@ -124,7 +194,7 @@ impl TreeGrid {
         view_distance
     }
 
-    fn view_dir(&self, pos: (usize, usize), dir: LookDir) -> LineIter {
+    fn view_dir(&self, pos: (usize, usize), dir: LookDir) -> LineIter<'_> {
         match dir {
             LookDir::Up => LineIter {
                 grid: self,
@@ -136,14 +206,14 @@ impl TreeGrid {
             LookDir::Right => LineIter {
                 grid: self,
                 pos,
-                pos_back: (self.width - 1, pos.1),
+                pos_back: (self.trees.width() - 1, pos.1),
                 dir: (1, 0),
                 is_finished: false,
             },
             LookDir::Down => LineIter {
                 grid: self,
                 pos,
-                pos_back: (pos.0, self.height - 1),
+                pos_back: (pos.0, self.trees.height() - 1),
                 dir: (0, 1),
                 is_finished: false,
             },
@@ -175,20 +245,33 @@ impl FromStr for TreeGrid {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut trees = vec![];
         let mut width = 0;
-        let mut height = 0;
-        for line in s.lines() {
-            width = line.len();
+        for (y, line) in s.lines().enumerate() {
+            if y == 0 {
+                width = line.len();
+            } else if width != line.len() {
+                bail!(
+                    "line {} '{}' has length {}, expected {}",
+                    y + 1,
+                    line,
+                    line.len(),
+                    width
+                );
+            }
+
             for tree in line.chars() {
-                let height = tree.to_digit(10).unwrap();
+                let height = tree
+                    .to_digit(10)
+                    .with_context(|| format!("invalid tree '{}' on line {}", tree, y + 1))?;
                 trees.push(height as u8);
             }
-            height += 1;
+        }
+
+        if trees.is_empty() {
+            bail!("empty tree grid");
         }
 
         let grid = Self {
-            trees,
-            height,
-            width,
+            trees: Grid::new(trees, width),
         };
         Ok(grid)
     }
@@ -280,34 +363,75 @@ impl<'a> DoubleEndedIterator for LineIter<'a> {
     }
 }
 
-pub fn day8(content: String) {
-    println!();
-    println!("==== Day 8 ====");
-    let grid = content.parse::<TreeGrid>().unwrap();
+pub fn day8(content: String) -> anyhow::Result<DayResult> {
+    let mut part1 = String::new();
+    let mut part2 = String::new();
+    let grid = content.parse::<TreeGrid>()?;
     let visibility = grid.visibility();
 
-    println!("Part 1");
     let visible = visibility.count_visible();
-    println!("Visible: {}", visible);
+    writeln!(part1, "Visible: {}", visible)?;
 
-    println!("Part 2");
-    println!("Best view score: {}", grid.max_visibility_score());
+    writeln!(part2, "Best view score: {}", grid.max_visibility_score())?;
+
+    Ok(DayResult::new(part1, Some(part2)))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    const EXAMPLE: &'static str = r#"30373
+    const EXAMPLE: &str = r#"30373
 25512
 65332
 33549
 35390"#;
 
-    const SIMPLE: &'static str = r#"123
+    const SIMPLE: &str = r#"123
 405
 678"#;
 
+    #[test]
+    fn test_ragged_grid() {
+        let error = "123\n45\n678".parse::<TreeGrid>().err().unwrap();
+        assert_eq!(error.to_string(), "line 2 '45' has length 2, expected 3");
+    }
+
+    #[test]
+    fn test_truncated_grid() {
+        assert!(EXAMPLE.parse::<TreeGrid>().is_ok());
+        assert!(SIMPLE.parse::<TreeGrid>().is_ok());
+
+        let truncated = &EXAMPLE[..EXAMPLE.len() - 2];
+        let error = truncated.parse::<TreeGrid>().err().unwrap();
+        assert_eq!(error.to_string(), "line 5 '353' has length 3, expected 5");
+
+        let error = "123\n4x6".parse::<TreeGrid>().err().unwrap();
+        assert_eq!(error.to_string(), "invalid tree 'x' on line 2");
+        assert!("".parse::<TreeGrid>().is_err());
+    }
+
+    #[test]
+    fn test_row_column() {
+        let grid = EXAMPLE.parse::<TreeGrid>().unwrap();
+        assert_eq!(grid.row(0), Some(&[3, 0, 3, 7, 3][..]));
+        assert_eq!(grid.row(4), Some(&[3, 5, 3, 9, 0][..]));
+        assert_eq!(grid.row(5), None);
+        assert_eq!(grid.column(0), vec![3, 2, 6, 3, 3]);
+        assert_eq!(grid.column(4), vec![3, 2, 2, 9, 0]);
+        assert!(grid.column(5).is_empty());
+
+        // Both agree with the line iterators
+        for (y, line) in grid.rows().enumerate() {
+            let heights = line.map(|(tree, _)| tree).collect::<Vec<_>>();
+            assert_eq!(grid.row(y).unwrap(), heights);
+        }
+        for (x, line) in grid.columns().enumerate() {
+            let heights = line.map(|(tree, _)| tree).collect::<Vec<_>>();
+            assert_eq!(grid.column(x), heights);
+        }
+    }
+
     #[test]
     fn test_part_1_simple() {
         let grid = SIMPLE.parse::<TreeGrid>().unwrap();
@@ -322,10 +446,36 @@ mod tests {
         assert_eq!(visibility.count_visible(), 21);
     }
 
+    #[test]
+    fn test_visibility_with_diagonals() {
+        let grid = EXAMPLE.parse::<TreeGrid>().unwrap();
+        assert_eq!(grid.visibility().count_visible(), 21);
+        // Only the 4 in the fourth row is additionally visible, from the bottom right corner
+        let visibility = grid.visibility_with_diagonals();
+        assert_eq!(visibility.count_visible(), 22);
+        assert!(visibility.visible[grid.index(3, 3).unwrap()]);
+
+        // Every inner tree of the simple grid is hidden in all eight directions
+        let grid = SIMPLE.parse::<TreeGrid>().unwrap();
+        assert_eq!(grid.visibility_with_diagonals().count_visible(), 8);
+    }
+
     #[test]
     fn test_part_2() {
         let grid = EXAMPLE.parse::<TreeGrid>().unwrap();
         let max_visibility = grid.max_visibility_score();
         assert_eq!(max_visibility, 8);
     }
+
+    #[test]
+    fn test_best_scenic_position() {
+        let grid = EXAMPLE.parse::<TreeGrid>().unwrap();
+        assert_eq!(grid.best_scenic_position(), ((2, 3), 8));
+    }
+
+    #[test]
+    fn test_day8_output() {
+        let output = day8(EXAMPLE.to_string()).unwrap();
+        assert!(output.part1.contains("Visible: 21"));
+    }
 }
@@ -1,7 +1,9 @@
+use crate::days::DayResult;
 use anyhow::bail;
 use itertools::Itertools;
 use na::Vector2;
 use std::collections::HashSet;
+use std::fmt::Write;
 use std::str::FromStr;
 
 enum Direction {
@@ -9,6 +11,10 @@ enum Direction {
     Right,
     Down,
     Left,
+    UpRight,
+    UpLeft,
+    DownRight,
+    DownLeft,
 }
 
 impl Direction {
@@ -18,6 +24,10 @@ impl Direction {
             Direction::Right => Vector2::new(1, 0),
             Direction::Down => Vector2::new(0, -1),
             Direction::Left => Vector2::new(-1, 0),
+            Direction::UpRight => Vector2::new(1, 1),
+            Direction::UpLeft => Vector2::new(-1, 1),
+            Direction::DownRight => Vector2::new(1, -1),
+            Direction::DownLeft => Vector2::new(-1, -1),
         }
     }
 }
@@ -31,6 +41,10 @@ impl FromStr for Direction {
             "R" => Direction::Right,
             "D" => Direction::Down,
             "L" => Direction::Left,
+            "UR" => Direction::UpRight,
+            "UL" => Direction::UpLeft,
+            "DR" => Direction::DownRight,
+            "DL" => Direction::DownLeft,
             dir => bail!("unsupported direction {}", dir),
         })
     }
@@ -54,6 +68,9 @@ impl FromStr for Command {
 
 struct RopeFollow {
     visited: HashSet<Vector2<i32>>,
+    /// Corners of the box around all visited positions, updated on every insert
+    min: Vector2<i32>,
+    max: Vector2<i32>,
     rope: Vec<Vector2<i32>>,
 }
 
@@ -64,19 +81,36 @@ impl RopeFollow {
         visited.insert(start);
         Self {
             visited,
+            min: start,
+            max: start,
             rope: vec![start; rope_length],
         }
     }
 
     fn execute_commands(&mut self, commands: &[Command]) {
+        self.execute_commands_with(commands, |_| {});
+    }
+
+    /// Like `execute_commands`, but calls `f` with every knot after each single step
+    fn execute_commands_with<F: FnMut(&[Vector2<i32>])>(&mut self, commands: &[Command], mut f: F) {
         for command in commands {
             for _ in 0..command.steps {
                 let dir = command.direction.to_vector();
                 self.step(&dir);
+                f(&self.rope);
             }
         }
     }
 
+    /// Like `execute_commands`, but returns every knot after each single step, e.g. for an
+    /// animation. Use `execute_commands_with` for large inputs.
+    #[allow(dead_code)]
+    fn execute_commands_traced(&mut self, commands: &[Command]) -> Vec<Vec<Vector2<i32>>> {
+        let mut frames = vec![];
+        self.execute_commands_with(commands, |rope| frames.push(rope.to_vec()));
+        frames
+    }
+
     fn step(&mut self, dir: &Vector2<i32>) {
         *self.rope.first_mut().unwrap() += dir;
         self.update_tail();
@@ -98,38 +132,88 @@ impl RopeFollow {
             head = *tail;
         }
 
-        self.visited.insert(*self.rope.last().unwrap());
+        let tail = *self.rope.last().unwrap();
+        self.visited.insert(tail);
+        self.min = self.min.inf(&tail);
+        self.max = self.max.sup(&tail);
     }
 
     fn count_visited(&self) -> usize {
         self.visited.len()
     }
+
+    /// All positions the tail has visited so far
+    #[allow(dead_code)]
+    fn visited_positions(&self) -> &HashSet<Vector2<i32>> {
+        &self.visited
+    }
+
+    /// Smallest and largest corner of the box around all visited positions
+    fn bounds(&self) -> (Vector2<i32>, Vector2<i32>) {
+        (self.min, self.max)
+    }
+
+    /// Current position of the last knot
+    #[allow(dead_code)]
+    fn tail(&self) -> Vector2<i32> {
+        *self.rope.last().unwrap()
+    }
+
+    /// Draws the rope like the puzzle description does, in a frame that covers all visited
+    /// positions and the current rope
+    #[allow(dead_code)]
+    fn render(&self) -> String {
+        let (min, max) = self
+            .rope
+            .iter()
+            .fold(self.bounds(), |(min, max), x| (min.inf(x), max.sup(x)));
+        let (min_x, max_x, min_y, max_y) = (min.x, max.x, min.y, max.y);
+
+        let mut frame = String::new();
+        // Higher y is further up
+        for y in (min_y..=max_y).rev() {
+            for x in min_x..=max_x {
+                let pos = Vector2::new(x, y);
+                let knot = self.rope.iter().position(|x| *x == pos);
+                let c = match knot {
+                    Some(0) => 'H',
+                    Some(_) if self.rope.len() == 2 => 'T',
+                    Some(index) => char::from_digit(index as u32, 10).unwrap_or('#'),
+                    None if pos == Vector2::zeros() => 's',
+                    None => '.',
+                };
+                frame.push(c);
+            }
+            frame.push('\n');
+        }
+        frame
+    }
 }
 
-pub fn day9(content: String) {
-    println!();
-    println!("==== Day 9 ====");
+pub fn day9(content: String) -> anyhow::Result<DayResult> {
+    let mut part1 = String::new();
+    let mut part2 = String::new();
     let commands = content
         .lines()
         .map(|x| x.parse::<Command>().unwrap())
         .collect_vec();
 
-    println!("Part 1");
     let mut rope = RopeFollow::new(2);
     rope.execute_commands(&commands);
-    println!("Tail visited positions: {}", rope.count_visited());
+    writeln!(part1, "Tail visited positions: {}", rope.count_visited())?;
 
-    println!("Part 2");
     let mut rope = RopeFollow::new(10);
     rope.execute_commands(&commands);
-    println!("Tail visited positions: {}", rope.count_visited());
+    writeln!(part2, "Tail visited positions: {}", rope.count_visited())?;
+
+    Ok(DayResult::new(part1, Some(part2)))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    const EXAMPLE: &'static str = r#"R 4
+    const EXAMPLE: &str = r#"R 4
 U 4
 L 3
 D 1
@@ -138,7 +222,7 @@ D 1
 L 5
 R 2"#;
 
-    const EXAMPLE_2: &'static str = r#"R 5
+    const EXAMPLE_2: &str = r#"R 5
 U 8
 L 8
 D 3
@@ -160,6 +244,23 @@ U 20"#;
         assert_eq!(rope.count_visited(), 13);
     }
 
+    #[test]
+    fn test_visited_positions() {
+        let commands = EXAMPLE
+            .lines()
+            .map(|x| x.parse::<Command>().unwrap())
+            .collect_vec();
+
+        let mut rope = RopeFollow::new(2);
+        rope.execute_commands(&commands);
+
+        let visited = rope.visited_positions();
+        assert_eq!(visited.len(), 13);
+        assert!(visited.contains(&Vector2::new(0, 0)));
+        assert!(visited.contains(&Vector2::new(3, 4)));
+        assert_eq!(rope.tail(), Vector2::new(1, 2));
+    }
+
     #[test]
     fn test_part_2_simple() {
         let commands = EXAMPLE
@@ -185,4 +286,96 @@ U 20"#;
 
         assert_eq!(rope.count_visited(), 36);
     }
+
+    #[test]
+    fn test_bounds() {
+        let commands = EXAMPLE_2
+            .lines()
+            .map(|x| x.parse::<Command>().unwrap())
+            .collect_vec();
+
+        let mut rope = RopeFollow::new(10);
+        rope.execute_commands(&commands);
+
+        let (min, max) = rope.bounds();
+        assert_eq!((min, max), (Vector2::new(-11, -5), Vector2::new(10, 6)));
+        for pos in rope.visited_positions() {
+            assert!(pos.x >= min.x && pos.y >= min.y);
+            assert!(pos.x <= max.x && pos.y <= max.y);
+        }
+        // The box is as small as possible
+        let visited = rope.visited_positions();
+        assert!(visited.iter().any(|x| x.x == min.x) && visited.iter().any(|x| x.x == max.x));
+        assert!(visited.iter().any(|x| x.y == min.y) && visited.iter().any(|x| x.y == max.y));
+    }
+
+    #[test]
+    fn test_diagonal_moves() {
+        let commands = ["UR 3", "R 1", "DL 2"]
+            .iter()
+            .map(|x| x.parse::<Command>().unwrap())
+            .collect_vec();
+
+        let mut rope = RopeFollow::new(2);
+        rope.execute_commands(&commands[..1]);
+        assert_eq!(rope.rope[0], Vector2::new(3, 3));
+        assert_eq!(rope.tail(), Vector2::new(2, 2));
+        assert_eq!(rope.count_visited(), 3);
+
+        rope.execute_commands(&commands[1..]);
+        assert_eq!(rope.rope[0], Vector2::new(2, 1));
+        assert_eq!(rope.tail(), Vector2::new(2, 2));
+        assert_eq!(rope.count_visited(), 4);
+    }
+
+    #[test]
+    fn test_execute_commands_traced() {
+        let commands = EXAMPLE
+            .lines()
+            .map(|x| x.parse::<Command>().unwrap())
+            .collect_vec();
+        let steps = commands.iter().map(|x| x.steps).sum::<usize>();
+
+        let mut rope = RopeFollow::new(10);
+        let frames = rope.execute_commands_traced(&commands);
+        assert_eq!(frames.len(), steps);
+        assert!(frames.iter().all(|x| x.len() == 10));
+        assert_eq!(frames[0][0], Vector2::new(1, 0));
+        assert_eq!(frames.last().unwrap(), &rope.rope);
+
+        let mut untraced = RopeFollow::new(10);
+        untraced.execute_commands(&commands);
+        assert_eq!(*frames.last().unwrap().last().unwrap(), untraced.tail());
+
+        // The callback sees the same frames without keeping them
+        let mut count = 0;
+        RopeFollow::new(10).execute_commands_with(&commands, |rope| {
+            assert_eq!(rope, frames[count]);
+            count += 1;
+        });
+        assert_eq!(count, steps);
+    }
+
+    #[test]
+    fn test_render() {
+        let commands = EXAMPLE
+            .lines()
+            .map(|x| x.parse::<Command>().unwrap())
+            .collect_vec();
+
+        let mut rope = RopeFollow::new(2);
+        rope.execute_commands(&commands);
+        assert_eq!(rope.render(), ".....\n.....\n.TH..\n.....\ns....\n");
+
+        let mut rope = RopeFollow::new(10);
+        rope.execute_commands(&commands);
+        // Knot 1 covers 2, knot 6 covers 7, 8, 9 and the start
+        assert_eq!(rope.render(), ".1H3\n.5..\n6...\n");
+    }
+
+    #[test]
+    fn test_day9_output() {
+        let output = day9(EXAMPLE.to_string()).unwrap();
+        assert!(output.part1.contains("Tail visited positions: 13"));
+    }
 }
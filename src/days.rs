@@ -0,0 +1,473 @@
+use crate::day1::{self, day1};
+use crate::day10::day10;
+use crate::day11::day11;
+use crate::day12::day12;
+use crate::day13::day13;
+use crate::day14::day14;
+use crate::day15::{self, day15};
+use crate::day16::{self, day16};
+use crate::day17::day17;
+use crate::day18::day18;
+use crate::day19::{self, day19};
+use crate::day2::day2;
+use crate::day20::{self, day20};
+use crate::day21::day21;
+use crate::day3::day3;
+use crate::day4::day4;
+use crate::day5::day5;
+use crate::day6::day6;
+use crate::day7::day7;
+use crate::day8::day8;
+use crate::day9::day9;
+use anyhow::{bail, Context};
+use std::fmt;
+use std::str::FromStr;
+
+/// Answers of both parts of a day
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DayResult {
+    pub part1: String,
+    /// `None` for days that don't solve part 2
+    pub part2: Option<String>,
+}
+
+impl DayResult {
+    /// Trailing newlines left over from writing the answers line by line are dropped
+    pub fn new(part1: String, part2: Option<String>) -> Self {
+        Self {
+            part1: part1.trim_end().to_string(),
+            part2: part2.map(|x| x.trim_end().to_string()),
+        }
+    }
+}
+
+impl fmt::Display for DayResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Part 1")?;
+        writeln!(f, "{}", self.part1)?;
+        if let Some(part2) = &self.part2 {
+            writeln!(f, "Part 2")?;
+            writeln!(f, "{}", part2)?;
+        }
+        Ok(())
+    }
+}
+
+/// One of the two parts of a day, e.g. `--part 2`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Part {
+    One,
+    Two,
+}
+
+impl FromStr for Part {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "1" => Part::One,
+            "2" => Part::Two,
+            part => bail!("invalid part '{}', expected 1 or 2", part),
+        })
+    }
+}
+
+impl fmt::Display for Part {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Part::One => write!(f, "1"),
+            Part::Two => write!(f, "2"),
+        }
+    }
+}
+
+/// A single puzzle day, which can be run on its input
+pub trait Day: Send + Sync {
+    fn number(&self) -> u32;
+
+    /// Solves both parts
+    fn run(&self, input: &str) -> anyhow::Result<DayResult>;
+
+    /// Solves only `part`. Days where both parts share most of the work just run both, the slow
+    /// ones override this to skip the other part.
+    fn run_part(&self, input: &str, part: Part) -> anyhow::Result<String> {
+        let result = self.run(input)?;
+        match part {
+            Part::One => Ok(result.part1),
+            Part::Two => result.part2.context("part 2 is not solved"),
+        }
+    }
+}
+
+/// All days, in order
+pub fn registry() -> Vec<Box<dyn Day>> {
+    vec![
+        Box::new(Day1),
+        Box::new(Day2),
+        Box::new(Day3),
+        Box::new(Day4),
+        Box::new(Day5),
+        Box::new(Day6),
+        Box::new(Day7),
+        Box::new(Day8),
+        Box::new(Day9),
+        Box::new(Day10),
+        Box::new(Day11),
+        Box::new(Day12),
+        Box::new(Day13),
+        Box::new(Day14),
+        Box::new(Day15),
+        Box::new(Day16),
+        Box::new(Day17),
+        Box::new(Day18),
+        Box::new(Day19),
+        Box::new(Day20),
+        Box::new(Day21),
+    ]
+}
+
+struct Day1;
+
+impl Day for Day1 {
+    fn number(&self) -> u32 {
+        1
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day1(input.to_string())
+    }
+
+    fn run_part(&self, input: &str, part: Part) -> anyhow::Result<String> {
+        match part {
+            Part::One => day1::part1(input),
+            Part::Two => day1::part2(input),
+        }
+    }
+}
+
+struct Day2;
+
+impl Day for Day2 {
+    fn number(&self) -> u32 {
+        2
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day2(input.to_string())
+    }
+}
+
+struct Day3;
+
+impl Day for Day3 {
+    fn number(&self) -> u32 {
+        3
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day3(input.to_string())
+    }
+}
+
+struct Day4;
+
+impl Day for Day4 {
+    fn number(&self) -> u32 {
+        4
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day4(input.to_string())
+    }
+}
+
+struct Day5;
+
+impl Day for Day5 {
+    fn number(&self) -> u32 {
+        5
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day5(input.to_string())
+    }
+}
+
+struct Day6;
+
+impl Day for Day6 {
+    fn number(&self) -> u32 {
+        6
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day6(input.to_string())
+    }
+}
+
+struct Day7;
+
+impl Day for Day7 {
+    fn number(&self) -> u32 {
+        7
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day7(input.to_string())
+    }
+}
+
+struct Day8;
+
+impl Day for Day8 {
+    fn number(&self) -> u32 {
+        8
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day8(input.to_string())
+    }
+}
+
+struct Day9;
+
+impl Day for Day9 {
+    fn number(&self) -> u32 {
+        9
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day9(input.to_string())
+    }
+}
+
+struct Day10;
+
+impl Day for Day10 {
+    fn number(&self) -> u32 {
+        10
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day10(input.to_string())
+    }
+}
+
+struct Day11;
+
+impl Day for Day11 {
+    fn number(&self) -> u32 {
+        11
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day11(input.to_string())
+    }
+}
+
+struct Day12;
+
+impl Day for Day12 {
+    fn number(&self) -> u32 {
+        12
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day12(input.to_string())
+    }
+}
+
+struct Day13;
+
+impl Day for Day13 {
+    fn number(&self) -> u32 {
+        13
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day13(input.to_string())
+    }
+}
+
+struct Day14;
+
+impl Day for Day14 {
+    fn number(&self) -> u32 {
+        14
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day14(input.to_string())
+    }
+}
+
+struct Day15;
+
+impl Day for Day15 {
+    fn number(&self) -> u32 {
+        15
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day15(input.to_string())
+    }
+
+    fn run_part(&self, input: &str, part: Part) -> anyhow::Result<String> {
+        match part {
+            Part::One => day15::part1(input),
+            Part::Two => day15::part2(input),
+        }
+    }
+}
+
+struct Day16;
+
+impl Day for Day16 {
+    fn number(&self) -> u32 {
+        16
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day16(input.to_string())
+    }
+
+    fn run_part(&self, input: &str, part: Part) -> anyhow::Result<String> {
+        match part {
+            Part::One => day16::part1(input),
+            Part::Two => day16::part2(input),
+        }
+    }
+}
+
+struct Day17;
+
+impl Day for Day17 {
+    fn number(&self) -> u32 {
+        17
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day17(input.to_string())
+    }
+}
+
+struct Day18;
+
+impl Day for Day18 {
+    fn number(&self) -> u32 {
+        18
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day18(input.to_string())
+    }
+}
+
+struct Day19;
+
+impl Day for Day19 {
+    fn number(&self) -> u32 {
+        19
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day19(input.to_string())
+    }
+
+    fn run_part(&self, input: &str, part: Part) -> anyhow::Result<String> {
+        match part {
+            Part::One => day19::part1(input),
+            Part::Two => day19::part2(input),
+        }
+    }
+}
+
+struct Day20;
+
+impl Day for Day20 {
+    fn number(&self) -> u32 {
+        20
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day20(input.to_string())
+    }
+
+    fn run_part(&self, input: &str, part: Part) -> anyhow::Result<String> {
+        match part {
+            Part::One => day20::part1(input),
+            Part::Two => day20::part2(input),
+        }
+    }
+}
+
+struct Day21;
+
+impl Day for Day21 {
+    fn number(&self) -> u32 {
+        21
+    }
+
+    fn run(&self, input: &str) -> anyhow::Result<DayResult> {
+        day21(input.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::Itertools;
+
+    #[test]
+    fn test_registry() {
+        let numbers = registry().iter().map(|x| x.number()).collect_vec();
+        assert_eq!(numbers, (1..=21).collect_vec());
+        assert!(numbers.iter().all_unique());
+    }
+
+    #[test]
+    fn test_display_result() {
+        let result = DayResult::new("Top: CMZ\n".to_string(), Some("Top: MCD\n".to_string()));
+        assert_eq!(result.to_string(), "Part 1\nTop: CMZ\nPart 2\nTop: MCD\n");
+
+        let result = DayResult::new("Score: 33".to_string(), None);
+        assert_eq!(result.to_string(), "Part 1\nScore: 33\n");
+    }
+
+    #[test]
+    fn test_parse_part() {
+        assert_eq!("1".parse::<Part>().unwrap(), Part::One);
+        assert_eq!("2".parse::<Part>().unwrap(), Part::Two);
+        let err = "3".parse::<Part>().err().unwrap();
+        assert_eq!(err.to_string(), "invalid part '3', expected 1 or 2");
+    }
+
+    #[test]
+    fn test_run_part() {
+        let input = "1000\n2000\n\n4000\n\n3000\n\n500";
+        assert_eq!(
+            Day1.run_part(input, Part::One).unwrap(),
+            "Elf with most calories: 2\nMost calories: 4000"
+        );
+        assert_eq!(
+            Day1.run_part(input, Part::Two).unwrap(),
+            "Top three calories: 10000"
+        );
+
+        // Days without their own split select from both parts
+        let both = Day6.run("mjqjpqmgbljsphdztnvjfqwrcgsmlb").unwrap();
+        let part2 = Day6.run_part("mjqjpqmgbljsphdztnvjfqwrcgsmlb", Part::Two);
+        assert_eq!(part2.unwrap(), both.part2.unwrap());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialize_result() {
+        let result = DayResult::new("Top: CMZ\n".to_string(), None);
+        assert_eq!(
+            serde_json::to_string(&result).unwrap(),
+            r#"{"part1":"Top: CMZ","part2":null}"#
+        );
+    }
+}
@@ -1,79 +1,344 @@
-use crate::day1::day1;
-use crate::day10::day10;
-use crate::day11::day11;
-use crate::day12::day12;
-use crate::day13::day13;
-use crate::day14::day14;
-use crate::day15::day15;
-use crate::day16::day16;
-use crate::day17::day17;
-use crate::day18::day18;
-use crate::day19::day19;
-use crate::day2::day2;
-use crate::day20::day20;
-use crate::day3::day3;
-use crate::day4::day4;
-use crate::day5::day5;
-use crate::day6::day6;
-use crate::day7::day7;
-use crate::day8::day8;
-use crate::day9::day9;
+use advent_of_code::days::{registry, Day, Part};
+use anyhow::{bail, Context};
+use itertools::Itertools;
+use rayon::prelude::*;
 use std::fs;
 use std::io::Read;
+use std::time::{Duration, Instant};
 
-extern crate core;
-extern crate nalgebra as na;
-
-mod day1;
-mod day10;
-mod day11;
-mod day12;
-mod day13;
-mod day14;
-mod day15;
-mod day16;
-mod day17;
-mod day18;
-mod day19;
-mod day2;
-mod day20;
-mod day21;
-mod day3;
-mod day4;
-mod day5;
-mod day6;
-mod day7;
-mod day8;
-mod day9;
-mod utils;
-
-fn main() {
-    day1(load_to_string("inputs/day1.txt")).unwrap();
-    day2(load_to_string("inputs/day2.txt")).unwrap();
-    day3(load_to_string("inputs/day3.txt")).unwrap();
-    day4(load_to_string("inputs/day4.txt"));
-    day5(load_to_string("inputs/day5.txt"));
-    day6(load_to_string("inputs/day6.txt"));
-    day7(load_to_string("inputs/day7.txt"));
-    day8(load_to_string("inputs/day8.txt"));
-    day9(load_to_string("inputs/day9.txt"));
-    day10(load_to_string("inputs/day10.txt"));
-    day11(load_to_string("inputs/day11.txt"));
-    day12(load_to_string("inputs/day12.txt"));
-    day13(load_to_string("inputs/day13.txt"));
-    day14(load_to_string("inputs/day14.txt"));
-    day15(load_to_string("inputs/day15.txt"));
-    day16(load_to_string("inputs/day16.txt"));
-    day17(load_to_string("inputs/day17.txt"));
-    day18(load_to_string("inputs/day18.txt"));
-    day19(load_to_string("inputs/day19.txt"));
-    day20(load_to_string("inputs/day20.txt"));
+/// Command line options, e.g. `cargo run -- 1 2 5 --timed --parallel`,
+/// `cat input | cargo run -- 6 --stdin` or `cargo run -- 16 --part 1`
+#[derive(Debug, Default, Eq, PartialEq)]
+struct Args {
+    /// Days to run, all of them if empty
+    days: Vec<u32>,
+    /// Print how long each day took to run
+    timed: bool,
+    /// Run the days on all cores, the output is still printed in day order
+    parallel: bool,
+    /// Read the input of the single selected day from stdin instead of its input file
+    stdin: bool,
+    /// Only solve this part of each selected day
+    part: Option<Part>,
 }
 
-fn load_to_string(path: &str) -> String {
-    let mut file = fs::File::open(path).expect("could not open file");
+impl Args {
+    fn parse(
+        args: impl IntoIterator<Item = String>,
+        registry: &[Box<dyn Day>],
+    ) -> anyhow::Result<Self> {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--timed" => parsed.timed = true,
+                "--parallel" => parsed.parallel = true,
+                "--stdin" => parsed.stdin = true,
+                "--part" => {
+                    let part = args.next().context("--part requires a value")?;
+                    parsed.part = Some(part.parse()?);
+                }
+                _ if arg.starts_with("--") => bail!("unknown option '{}'", arg),
+                _ => {
+                    let day = arg
+                        .parse::<u32>()
+                        .with_context(|| format!("invalid day '{}'", arg))?;
+                    if !registry.iter().any(|x| x.number() == day) {
+                        bail!("day {} is not implemented", day);
+                    }
+                    parsed.days.push(day);
+                }
+            }
+        }
+
+        if parsed.stdin && parsed.days.len() != 1 {
+            bail!("--stdin requires exactly one day");
+        }
+        Ok(parsed)
+    }
+
+    fn is_selected(&self, day: u32) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let days = registry();
+    let args = Args::parse(std::env::args().skip(1), &days)?;
+
+    let selected = days
+        .iter()
+        .map(|x| x.as_ref())
+        .filter(|x| args.is_selected(x.number()))
+        .collect_vec();
+    let stdin = if args.stdin {
+        Some(read_input(std::io::stdin().lock()).context("could not read stdin")?)
+    } else {
+        None
+    };
+    let runs = run_days(
+        &selected,
+        |day| match &stdin {
+            Some(input) => Ok(input.clone()),
+            None => load_input(day),
+        },
+        args.part,
+        args.parallel,
+    );
+
+    let mut timings = vec![];
+    for run in runs {
+        // A missing input or a failing day shouldn't keep the other days from running
+        match run.result {
+            Ok(output) => {
+                println!();
+                println!("==== Day {} ====", run.number);
+                print!("{}", output);
+            }
+            Err(err) => println!("Day {} skipped: {:#}", run.number, err),
+        }
+        if let Some(duration) = run.duration {
+            timings.push((run.number, duration));
+        }
+    }
+
+    if args.timed {
+        print_timings(&timings);
+    }
+
+    Ok(())
+}
+
+/// Outcome of running a single day
+struct DayRun {
+    number: u32,
+    /// Time spent solving, if the input could be loaded
+    duration: Option<Duration>,
+    /// The answers, formatted for printing
+    result: anyhow::Result<String>,
+}
+
+/// Runs every day on the input returned by `load`, returning the runs in the same order as
+/// `days` even when they run in parallel. With a `part`, only that part of each day is solved.
+fn run_days(
+    days: &[&dyn Day],
+    load: impl Fn(u32) -> anyhow::Result<String> + Sync,
+    part: Option<Part>,
+    parallel: bool,
+) -> Vec<DayRun> {
+    let run_day = |day: &&dyn Day| {
+        let mut duration = None;
+        let result = load(day.number()).and_then(|input| {
+            let (result, elapsed) = timed(|| match part {
+                Some(part) => day
+                    .run_part(&input, part)
+                    .map(|answer| format!("Part {}\n{}\n", part, answer)),
+                None => day.run(&input).map(|result| result.to_string()),
+            });
+            duration = Some(elapsed);
+            result
+        });
+        DayRun {
+            number: day.number(),
+            duration,
+            result,
+        }
+    };
+
+    if parallel {
+        days.par_iter().map(run_day).collect()
+    } else {
+        days.iter().map(run_day).collect()
+    }
+}
+
+/// Runs `f` and measures how long it took
+fn timed<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let result = f();
+    (result, start.elapsed())
+}
+
+fn print_timings(timings: &[(u32, Duration)]) {
+    println!();
+    println!("Day | Duration");
+    for (day, duration) in timings {
+        println!("{:>3} | {:>10.3?}", day, duration);
+    }
+    let total = timings
+        .iter()
+        .map(|(_, duration)| *duration)
+        .sum::<Duration>();
+    println!("Total {:>10.3?}", total);
+}
+
+/// Puzzle inputs are from this year's event
+#[cfg(feature = "download")]
+const YEAR: u32 = 2022;
+
+/// Loads `inputs/dayN.txt`. With the `download` feature, missing inputs are downloaded first if
+/// the `AOC_SESSION` environment variable holds a session cookie.
+fn load_input(day: u32) -> anyhow::Result<String> {
+    #[cfg(feature = "download")]
+    if let Ok(session) = std::env::var("AOC_SESSION") {
+        return advent_of_code::utils::download_input(YEAR, day, &session);
+    }
+
+    load_to_string(&format!("inputs/day{}.txt", day))
+}
+
+fn load_to_string(path: &str) -> anyhow::Result<String> {
+    let file = fs::File::open(path).with_context(|| format!("could not open '{}'", path))?;
+    read_input(file).with_context(|| format!("could not read '{}' to string", path))
+}
+
+/// Reads everything from `reader`, e.g. a file or stdin
+fn read_input(mut reader: impl Read) -> anyhow::Result<String> {
     let mut output = String::new();
-    file.read_to_string(&mut output)
-        .expect("could not read to string");
-    output
+    reader.read_to_string(&mut output)?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> anyhow::Result<Args> {
+        Args::parse(args.iter().map(|x| x.to_string()), &registry())
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = parse(&["16"]).unwrap();
+        assert_eq!(args.days, vec![16]);
+        assert!(args.is_selected(16));
+        assert!(!args.is_selected(15));
+
+        let args = parse(&["1", "2", "5"]).unwrap();
+        assert_eq!(args.days, vec![1, 2, 5]);
+
+        // No days runs all of them
+        let args = parse(&[]).unwrap();
+        assert!((1..=21).all(|x| args.is_selected(x)));
+    }
+
+    #[test]
+    fn test_parse_invalid_args() {
+        let err = parse(&["99"]).err().unwrap();
+        assert_eq!(err.to_string(), "day 99 is not implemented");
+
+        let err = parse(&["day1"]).err().unwrap();
+        assert_eq!(err.to_string(), "invalid day 'day1'");
+
+        let err = parse(&["--fast"]).err().unwrap();
+        assert_eq!(err.to_string(), "unknown option '--fast'");
+    }
+
+    #[test]
+    fn test_parse_timed() {
+        let args = parse(&["16", "--timed"]).unwrap();
+        assert_eq!(args.days, vec![16]);
+        assert!(args.timed);
+        assert!(!parse(&["16"]).unwrap().timed);
+
+        let args = parse(&["--parallel", "1"]).unwrap();
+        assert_eq!(args.days, vec![1]);
+        assert!(args.parallel && !args.timed);
+    }
+
+    #[test]
+    fn test_parallel_matches_sequential() {
+        let days = registry();
+        let days = days.iter().map(|x| x.as_ref()).collect_vec();
+        // Only give the quick days an input, the others fail to load
+        let load = |day| match day {
+            1 => Ok("1000\n2000\n\n4000".to_string()),
+            5 => Ok("    [D]\n[N] [C]\n 1   2\n\nmove 1 from 2 to 1".to_string()),
+            6 => Ok("mjqjpqmgbljsphdztnvjfqwrcgsmlb".to_string()),
+            day => bail!("no input for day {}", day),
+        };
+        let results = |parallel| {
+            run_days(&days, load, None, parallel)
+                .into_iter()
+                .map(|run| (run.number, run.result.map_err(|x| x.to_string())))
+                .collect_vec()
+        };
+
+        let sequential = results(false);
+        assert_eq!(sequential.len(), 21);
+        assert!(sequential[0]
+            .1
+            .as_ref()
+            .unwrap()
+            .contains("Elf with most calories: 2"));
+        assert_eq!(sequential[1].1, Err("no input for day 2".to_string()));
+        assert_eq!(results(true), sequential);
+    }
+
+    #[test]
+    fn test_parse_part() {
+        let args = parse(&["16", "--part", "1"]).unwrap();
+        assert_eq!(args.days, vec![16]);
+        assert_eq!(args.part, Some(Part::One));
+        assert_eq!(parse(&["16"]).unwrap().part, None);
+
+        let err = parse(&["16", "--part"]).err().unwrap();
+        assert_eq!(err.to_string(), "--part requires a value");
+    }
+
+    #[test]
+    fn test_run_single_part() {
+        let days = registry();
+        let day1 = days[0].as_ref();
+        let runs = run_days(
+            &[day1],
+            |_| Ok("1000\n2000\n\n4000".to_string()),
+            Some(Part::Two),
+            false,
+        );
+        assert_eq!(
+            runs[0].result.as_ref().unwrap(),
+            "Part 2\nTop three calories: 7000\n"
+        );
+    }
+
+    #[test]
+    fn test_timed() {
+        let sleep = Duration::from_millis(20);
+        let (result, duration) = timed(|| {
+            std::thread::sleep(sleep);
+            42
+        });
+        assert_eq!(result, 42);
+        assert!(duration >= sleep);
+    }
+
+    #[test]
+    fn test_parse_stdin() {
+        let args = parse(&["6", "--stdin"]).unwrap();
+        assert_eq!(args.days, vec![6]);
+        assert!(args.stdin);
+
+        for args in [&["--stdin"][..], &["1", "2", "--stdin"]] {
+            let err = parse(args).err().unwrap();
+            assert_eq!(err.to_string(), "--stdin requires exactly one day");
+        }
+    }
+
+    #[test]
+    fn test_read_input() {
+        let content = "mjqjpqmgbljsphdztnvjfqwrcgsmlb\nsecond line\n";
+        let input = read_input(std::io::Cursor::new(content)).unwrap();
+        assert_eq!(input, content);
+
+        assert!(read_input(std::io::Cursor::new([0xff, 0xfe])).is_err());
+    }
+
+    #[test]
+    fn test_load_missing_input() {
+        let err = load_to_string("inputs/day99.txt").err().unwrap();
+        assert!(format!("{:#}", err).contains("inputs/day99.txt"));
+
+        assert!(load_to_string("inputs/day6.txt").is_ok());
+    }
 }
@@ -1,19 +1,297 @@
 #![allow(dead_code)]
 
-use na::{Point2, Scalar};
+use anyhow::{bail, Context};
+use itertools::Itertools;
+use na::{Point2, Point3, Scalar, Vector2};
+use nom::{Finish, IResult};
 use num_traits::bounds::LowerBounded;
-use num_traits::Signed;
+use num_traits::{PrimInt, Signed};
 use rustc_hash::FxHashSet;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs;
 use std::hash::Hash;
+use std::path::Path;
+
+/// Offsets to adjacent positions, for every signed coordinate type
+pub trait Directions: Scalar {
+    /// Offsets to the horizontally and vertically adjacent positions
+    const DIRECTIONS_4: [Vector2<Self>; 4];
+    /// Offsets to all adjacent positions, `DIRECTIONS_4` followed by the diagonals
+    const DIRECTIONS_8: [Vector2<Self>; 8];
+}
+
+macro_rules! impl_directions {
+    ($($t:ty),*) => {
+        $(
+            impl Directions for $t {
+                const DIRECTIONS_4: [Vector2<Self>; 4] = [
+                    Vector2::new(1, 0),
+                    Vector2::new(-1, 0),
+                    Vector2::new(0, 1),
+                    Vector2::new(0, -1),
+                ];
+                const DIRECTIONS_8: [Vector2<Self>; 8] = [
+                    Vector2::new(1, 0),
+                    Vector2::new(-1, 0),
+                    Vector2::new(0, 1),
+                    Vector2::new(0, -1),
+                    Vector2::new(1, 1),
+                    Vector2::new(1, -1),
+                    Vector2::new(-1, 1),
+                    Vector2::new(-1, -1),
+                ];
+            }
+        )*
+    };
+}
+
+impl_directions!(i8, i16, i32, i64, isize);
+
+/// Loads the example from the puzzle description of `day`, stored in `examples/dayN.txt`
+pub fn load_example(day: u32) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("examples/day{}.txt", day));
+    fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("could not load {}: {}", path.display(), err))
+}
+
+/// Returns the content of `path`, calling `fetch` and caching its result at `path` first if the
+/// file doesn't exist yet
+pub fn load_or_fetch(
+    path: &Path,
+    fetch: impl FnOnce() -> anyhow::Result<String>,
+) -> anyhow::Result<String> {
+    if path.exists() {
+        return fs::read_to_string(path)
+            .with_context(|| format!("could not read '{}'", path.display()));
+    }
+
+    let content = fetch()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, &content).with_context(|| format!("could not cache '{}'", path.display()))?;
+    Ok(content)
+}
+
+/// Downloads the puzzle input of `day` with the `session` cookie of a logged in user, unless it
+/// is already cached in `inputs/dayN.txt`
+#[cfg(feature = "download")]
+pub fn download_input(year: u32, day: u32, session: &str) -> anyhow::Result<String> {
+    let path = format!("inputs/day{}.txt", day);
+    load_or_fetch(Path::new(&path), || {
+        let url = format!("https://adventofcode.com/{}/day/{}/input", year, day);
+        let input = reqwest::blocking::Client::new()
+            .get(&url)
+            .header(reqwest::header::COOKIE, format!("session={}", session))
+            .send()
+            .and_then(|x| x.error_for_status())
+            .and_then(|x| x.text())
+            .with_context(|| format!("could not download {}", url))?;
+        Ok(input)
+    })
+}
+
+/// Runs `parser` on `line` with its whitespace collapsed to single spaces. Errors name the part of
+/// the line where parsing stopped, and the whole line has to be consumed.
+pub fn parse_normalized<T>(
+    line: &str,
+    mut parser: impl FnMut(&str) -> IResult<&str, T>,
+) -> anyhow::Result<T> {
+    let normalized = line.split_whitespace().join(" ");
+    match parser(&normalized).finish() {
+        Ok(("", value)) => Ok(value),
+        Ok((remaining, _)) => bail!("unexpected '{}' at the end of '{}'", remaining, normalized),
+        Err(err) if err.input.is_empty() => bail!("'{}' ends too early", normalized),
+        Err(err) => bail!("could not parse '{}' at '{}'", normalized, err.input),
+    }
+}
+
+/// The `k` largest items, largest first. Only `k` items are kept at once, so this takes
+/// O(n log k) instead of sorting everything.
+pub fn top_k<T: Ord>(iter: impl IntoIterator<Item = T>, k: usize) -> Vec<T> {
+    if k == 0 {
+        return vec![];
+    }
+
+    // Min heap of the largest items so far, the smallest of them is replaced first
+    let mut heap = BinaryHeap::with_capacity(k + 1);
+    for item in iter {
+        heap.push(Reverse(item));
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+    heap.into_sorted_vec().into_iter().map(|x| x.0).collect()
+}
 
 pub fn manhattan_distance<N: Scalar + Signed>(left: &Point2<N>, right: &Point2<N>) -> N {
     (left.x.clone() - right.x.clone()).abs() + (left.y.clone() - right.y.clone()).abs()
 }
 
+pub fn manhattan_distance_3<N: Scalar + Signed>(left: &Point3<N>, right: &Point3<N>) -> N {
+    (left.x.clone() - right.x.clone()).abs()
+        + (left.y.clone() - right.y.clone()).abs()
+        + (left.z.clone() - right.z.clone()).abs()
+}
+
+/// Dense 2D grid stored row by row, addressed by points of any integer type
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Grid<T> {
+    cells: Vec<T>,
+    width: usize,
+    height: usize,
+}
+
+impl<T> Grid<T> {
+    /// Creates a grid from its cells in row order, `cells` must fill whole rows
+    pub fn new(cells: Vec<T>, width: usize) -> Self {
+        assert!(width > 0, "grid must be at least one cell wide");
+        assert_eq!(cells.len() % width, 0, "cells must fill whole rows");
+        let height = cells.len() / width;
+        Self {
+            cells,
+            width,
+            height,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn cells(&self) -> &[T] {
+        &self.cells
+    }
+
+    pub fn in_bounds<N: Scalar + PrimInt>(&self, pos: &Point2<N>) -> bool {
+        self.index(pos).is_some()
+    }
+
+    pub fn index<N: Scalar + PrimInt>(&self, pos: &Point2<N>) -> Option<usize> {
+        // Negative coordinates don't convert
+        let x = pos.x.to_usize()?;
+        let y = pos.y.to_usize()?;
+        (x < self.width && y < self.height).then(|| x + y * self.width)
+    }
+
+    /// Inverse of `index`
+    pub fn pos<N: Scalar + PrimInt>(&self, index: usize) -> Option<Point2<N>> {
+        if index >= self.cells.len() {
+            return None;
+        }
+        Some(Point2::new(
+            N::from(index % self.width)?,
+            N::from(index / self.width)?,
+        ))
+    }
+
+    pub fn get<N: Scalar + PrimInt>(&self, pos: &Point2<N>) -> Option<&T> {
+        self.cells.get(self.index(pos)?)
+    }
+
+    /// Replaces the cell at `pos` and returns the old value, or `None` if `pos` is out of bounds
+    pub fn set<N: Scalar + PrimInt>(&mut self, pos: &Point2<N>, value: T) -> Option<T> {
+        let index = self.index(pos)?;
+        Some(std::mem::replace(&mut self.cells[index], value))
+    }
+
+    /// Horizontally and vertically adjacent positions that are inside the grid
+    pub fn neighbors4<N: Scalar + PrimInt>(&self, pos: &Point2<N>) -> Vec<Point2<N>> {
+        if !self.in_bounds(pos) {
+            return vec![];
+        }
+
+        let (x, y) = (pos.x, pos.y);
+        let mut neighbors = vec![];
+        if x.to_usize().unwrap() + 1 < self.width {
+            neighbors.push(Point2::new(x + N::one(), y));
+        }
+        if x > N::zero() {
+            neighbors.push(Point2::new(x - N::one(), y));
+        }
+        if y.to_usize().unwrap() + 1 < self.height {
+            neighbors.push(Point2::new(x, y + N::one()));
+        }
+        if y > N::zero() {
+            neighbors.push(Point2::new(x, y - N::one()));
+        }
+        neighbors
+    }
+}
+
+impl<T: Clone> Grid<T> {
+    pub fn filled(width: usize, height: usize, value: T) -> Self {
+        Self::new(vec![value; width * height], width)
+    }
+}
+
+/// Performs a breadth first search from `start` until a node satisfying `is_goal` is found.
+/// Returns the path to it, both ends included, and its length in steps.
+///
+/// `successors` is called exactly once for every node visited before the goal, in the order they
+/// were reached, so without a goal it is called for everything reachable from `start`.
+///
+/// N: Node type
+/// FN: Successor
+/// IN: IntoIterator over successors
+/// F: Goal check
+pub fn bfs<
+    N: Clone + Eq + Hash,
+    FN: FnMut(&N) -> IN,
+    IN: IntoIterator<Item = N>,
+    F: FnMut(&N) -> bool,
+>(
+    start: N,
+    mut successors: FN,
+    mut is_goal: F,
+) -> Option<(Vec<N>, usize)> {
+    let mut seen = FxHashSet::default();
+    seen.insert(start.clone());
+
+    // Doubles as the queue, every node remembers the index of the node it was reached from
+    let mut nodes = vec![(start, 0)];
+    let mut next = 0;
+    while let Some((node, _)) = nodes.get(next) {
+        let node = node.clone();
+        if is_goal(&node) {
+            let mut path = vec![];
+            let mut index = next;
+            loop {
+                let (node, parent) = &nodes[index];
+                path.push(node.clone());
+                if index == 0 {
+                    break;
+                }
+                index = *parent;
+            }
+            path.reverse();
+
+            let length = path.len() - 1;
+            return Some((path, length));
+        }
+
+        for successor in successors(&node) {
+            if seen.insert(successor.clone()) {
+                nodes.push((successor, next));
+            }
+        }
+        next += 1;
+    }
+
+    None
+}
+
 /// Performs a depth first search on the input graph.
 /// Returns the first leaf node with the highest score found.
 ///
-/// This function assumes a graph without circles.
+/// Circles are cut off by remembering every visited node, but nodes that keep changing, e.g. by
+/// counting steps, can make the search go on forever. Use `dfs_with_path` with `max_iterations`
+/// for those.
 ///
 /// T: Node type
 /// FN: Successor
@@ -31,19 +309,129 @@ pub fn dfs<
     F: FnMut(&N) -> bool,
 >(
     start: N,
+    successors: FN,
+    score: SF,
+    best_possible_score: BSF,
+    is_final: F,
+) -> SC {
+    let (best_score, _, _) = depth_first_search(
+        start,
+        SC::min_value(),
+        None,
+        false,
+        successors,
+        score,
+        best_possible_score,
+        is_final,
+    );
+    best_score
+}
+
+/// Like `dfs`, but also returns the nodes from `start` to the best leaf, both included. Starts
+/// from `initial_best`, e.g. a score known to be achievable from a heuristic, so more of the
+/// graph can be pruned early. The path is `None` if no leaf scored above it.
+///
+/// With `max_iterations`, the search stops after expanding that many states and returns the best
+/// leaf found until then.
+pub fn dfs_with_path<
+    N: Clone + Eq + Hash,
+    FN: FnMut(&N) -> IN,
+    IN: IntoIterator<Item = N>,
+    SF: FnMut(&N) -> SC,
+    BSF: FnMut(&N) -> SC,
+    SC: Ord,
+    F: FnMut(&N) -> bool,
+>(
+    start: N,
+    initial_best: SC,
+    max_iterations: Option<usize>,
+    successors: FN,
+    score: SF,
+    best_possible_score: BSF,
+    is_final: F,
+) -> (SC, Option<Vec<N>>) {
+    let (best_score, best_path, _) = depth_first_search(
+        start,
+        initial_best,
+        max_iterations,
+        true,
+        successors,
+        score,
+        best_possible_score,
+        is_final,
+    );
+    (best_score, best_path)
+}
+
+/// Like `dfs_with_path` without the path, but also returns how many states were expanded, so
+/// tests can check how much a bound or an initial best prunes
+#[cfg(test)]
+pub(crate) fn dfs_expanded<
+    N: Clone + Eq + Hash,
+    FN: FnMut(&N) -> IN,
+    IN: IntoIterator<Item = N>,
+    SF: FnMut(&N) -> SC,
+    BSF: FnMut(&N) -> SC,
+    SC: Ord,
+    F: FnMut(&N) -> bool,
+>(
+    start: N,
+    initial_best: SC,
+    successors: FN,
+    score: SF,
+    best_possible_score: BSF,
+    is_final: F,
+) -> (SC, usize) {
+    let (best_score, _, expanded) = depth_first_search(
+        start,
+        initial_best,
+        None,
+        false,
+        successors,
+        score,
+        best_possible_score,
+        is_final,
+    );
+    (best_score, expanded)
+}
+
+/// Shared search loop of `dfs` and `dfs_with_path`, also returning how many states were
+/// expanded. Parents are only remembered with `keep_path`, otherwise the returned path is always
+/// `None`.
+#[allow(clippy::too_many_arguments)]
+fn depth_first_search<
+    N: Clone + Eq + Hash,
+    FN: FnMut(&N) -> IN,
+    IN: IntoIterator<Item = N>,
+    SF: FnMut(&N) -> SC,
+    BSF: FnMut(&N) -> SC,
+    SC: Ord,
+    F: FnMut(&N) -> bool,
+>(
+    start: N,
+    initial_best: SC,
+    max_iterations: Option<usize>,
+    keep_path: bool,
     mut successors: FN,
     mut score: SF,
     mut best_possible_score: BSF,
     mut is_final: F,
-) -> SC {
+) -> (SC, Option<Vec<N>>, usize) {
     let mut visited = FxHashSet::default();
+    // Every visited node with the index of the node it was reached from, if `keep_path` is set
+    let mut nodes: Vec<(N, Option<usize>)> = Vec::new();
     let mut stack = Vec::new();
-    stack.push(start);
+    stack.push((start, None));
 
-    let mut best_score = SC::min_value();
-    // let mut best_path = None;
+    let mut expanded = 0;
+    let mut best_score = initial_best;
+    let mut best_leaf = None;
+
+    while let Some((node, parent)) = stack.pop() {
+        if max_iterations.is_some_and(|max| expanded >= max) {
+            break;
+        }
 
-    while let Some(node) = stack.pop() {
         if best_possible_score(&node) <= best_score {
             continue;
         }
@@ -52,19 +440,388 @@ pub fn dfs<
             continue;
         }
 
+        expanded += 1;
+        let index = keep_path.then(|| {
+            nodes.push((node.clone(), parent));
+            nodes.len() - 1
+        });
+
         if is_final(&node) {
             // This is a leaf node
             let score_value = score(&node);
             if score_value > best_score {
                 best_score = score_value;
-
-                // best_path = Some(path);
+                best_leaf = index;
             }
             continue;
         }
 
-        stack.extend(successors(&node));
+        stack.extend(successors(&node).into_iter().map(|x| (x, index)));
     }
 
+    let best_path = best_leaf.map(|leaf| {
+        let mut path = vec![];
+        let mut index = Some(leaf);
+        while let Some(current) = index {
+            let (node, parent) = &nodes[current];
+            path.push(node.clone());
+            index = *parent;
+        }
+        path.reverse();
+        path
+    });
+
+    (best_score, best_path, expanded)
+}
+
+/// Like `dfs`, but always expands the node with the highest `best_possible_score` next, so good
+/// leaves are found early and more of the graph can be pruned. Takes more memory than `dfs`, as
+/// every node waiting to be expanded is kept in a heap.
+pub fn branch_and_bound<
+    N: Clone + Eq + Hash,
+    FN: FnMut(&N) -> IN,
+    IN: IntoIterator<Item = N>,
+    SF: FnMut(&N) -> SC,
+    BSF: FnMut(&N) -> SC,
+    SC: Ord + LowerBounded,
+    F: FnMut(&N) -> bool,
+>(
+    start: N,
+    successors: FN,
+    score: SF,
+    best_possible_score: BSF,
+    is_final: F,
+) -> SC {
+    let (best_score, _) =
+        best_first_search(start, successors, score, best_possible_score, is_final);
     best_score
 }
+
+/// Like `branch_and_bound`, but also returns how many states were expanded
+#[cfg(test)]
+pub(crate) fn branch_and_bound_expanded<
+    N: Clone + Eq + Hash,
+    FN: FnMut(&N) -> IN,
+    IN: IntoIterator<Item = N>,
+    SF: FnMut(&N) -> SC,
+    BSF: FnMut(&N) -> SC,
+    SC: Ord + LowerBounded,
+    F: FnMut(&N) -> bool,
+>(
+    start: N,
+    successors: FN,
+    score: SF,
+    best_possible_score: BSF,
+    is_final: F,
+) -> (SC, usize) {
+    best_first_search(start, successors, score, best_possible_score, is_final)
+}
+
+/// Search loop of `branch_and_bound`, also returning how many states were expanded
+fn best_first_search<
+    N: Clone + Eq + Hash,
+    FN: FnMut(&N) -> IN,
+    IN: IntoIterator<Item = N>,
+    SF: FnMut(&N) -> SC,
+    BSF: FnMut(&N) -> SC,
+    SC: Ord + LowerBounded,
+    F: FnMut(&N) -> bool,
+>(
+    start: N,
+    mut successors: FN,
+    mut score: SF,
+    mut best_possible_score: BSF,
+    mut is_final: F,
+) -> (SC, usize) {
+    let mut visited = FxHashSet::default();
+    // The heap only holds the bound and an index into `pending`, so nodes don't need to be `Ord`.
+    // Equal bounds prefer the node found last, which is usually the deepest one.
+    let mut pending = vec![Some(start)];
+    let mut heap = BinaryHeap::new();
+    heap.push((best_possible_score(pending[0].as_ref().unwrap()), 0));
+
+    let mut best_score = SC::min_value();
+
+    while let Some((bound, index)) = heap.pop() {
+        // Every other node is bounded even lower
+        if bound <= best_score {
+            break;
+        }
+
+        let node = pending[index].take().unwrap();
+        if !visited.insert(node.clone()) {
+            continue;
+        }
+
+        if is_final(&node) {
+            // This is a leaf node
+            best_score = best_score.max(score(&node));
+            continue;
+        }
+
+        for successor in successors(&node) {
+            let bound = best_possible_score(&successor);
+            if bound > best_score {
+                heap.push((bound, pending.len()));
+                pending.push(Some(successor));
+            }
+        }
+    }
+
+    (best_score, visited.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_normalized() {
+        fn parser(input: &str) -> IResult<&str, i64> {
+            nom::character::complete::i64(input)
+        }
+        assert_eq!(parse_normalized("  42 ", parser).unwrap(), 42);
+
+        let err = parse_normalized("x42", parser).err().unwrap();
+        assert_eq!(err.to_string(), "could not parse 'x42' at 'x42'");
+        let err = parse_normalized("42  x", parser).err().unwrap();
+        assert_eq!(err.to_string(), "unexpected ' x' at the end of '42 x'");
+        let err = parse_normalized("", parser).err().unwrap();
+        assert_eq!(err.to_string(), "'' ends too early");
+    }
+
+    #[test]
+    fn test_top_k() {
+        assert_eq!(top_k([5, 1, 9, 3, 7], 2), vec![9, 7]);
+        assert_eq!(top_k([5, 1, 9, 9, 7], 3), vec![9, 9, 7]);
+        assert_eq!(top_k([2, 1], 5), vec![2, 1]);
+        assert!(top_k([2, 1], 0).is_empty());
+        assert!(top_k(Vec::<u32>::new(), 3).is_empty());
+    }
+
+    #[test]
+    fn test_load_example() {
+        let example = load_example(13);
+        assert!(!example.is_empty());
+        assert!(example.starts_with("[1,1,3,1,1]"));
+    }
+
+    #[test]
+    fn test_load_or_fetch() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("inputs").join("day1.txt");
+
+        let content = load_or_fetch(&path, || Ok("fetched".to_string())).unwrap();
+        assert_eq!(content, "fetched");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fetched");
+
+        // The cached file is used without fetching again
+        let content = load_or_fetch(&path, || panic!("fetched a cached input")).unwrap();
+        assert_eq!(content, "fetched");
+
+        // Nothing is cached if fetching fails
+        let missing = dir.path().join("day2.txt");
+        assert!(load_or_fetch(&missing, || anyhow::bail!("offline")).is_err());
+        assert!(!missing.exists());
+    }
+
+    #[test]
+    fn test_manhattan_distance_3() {
+        let left = Point3::new(1, 2, 3);
+        let right = Point3::new(4, 0, 3);
+        assert_eq!(manhattan_distance_3(&left, &right), 5);
+        assert_eq!(manhattan_distance_3(&right, &left), 5);
+        assert_eq!(manhattan_distance_3(&left, &left), 0);
+    }
+
+    /// Neighbors on a 3x3 grid, without the walls
+    fn grid_successors(
+        (x, y): (i32, i32),
+        walls: &[(i32, i32)],
+    ) -> impl Iterator<Item = (i32, i32)> + '_ {
+        [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]
+            .into_iter()
+            .filter(|(x, y)| (0..3).contains(x) && (0..3).contains(y))
+            .filter(|pos| !walls.contains(pos))
+    }
+
+    #[test]
+    fn test_bfs() {
+        let (path, length) = bfs(
+            (0, 0),
+            |&pos| grid_successors(pos, &[]),
+            |&pos| pos == (2, 2),
+        )
+        .unwrap();
+        assert_eq!(length, 4);
+        assert_eq!(path.len(), 5);
+        assert_eq!(path.first(), Some(&(0, 0)));
+        assert_eq!(path.last(), Some(&(2, 2)));
+        for (from, to) in path.iter().zip(path.iter().skip(1)) {
+            assert_eq!((to.0 - from.0).abs() + (to.1 - from.1).abs(), 1);
+        }
+
+        // The only way around the walls goes through the bottom row
+        let walls = [(1, 0), (1, 1)];
+        let (path, length) = bfs(
+            (0, 0),
+            |&pos| grid_successors(pos, &walls),
+            |&pos| pos == (2, 0),
+        )
+        .unwrap();
+        assert_eq!(length, 6);
+        assert_eq!(
+            path,
+            vec![(0, 0), (0, 1), (0, 2), (1, 2), (2, 2), (2, 1), (2, 0)]
+        );
+    }
+
+    #[test]
+    fn test_bfs_unreachable() {
+        assert_eq!(
+            bfs((0, 0), |&pos| grid_successors(pos, &[]), |_| false),
+            None
+        );
+
+        let walls = [(1, 0), (1, 1), (1, 2)];
+        assert_eq!(
+            bfs(
+                (0, 0),
+                |&pos| grid_successors(pos, &walls),
+                |&pos| pos == (2, 2)
+            ),
+            None
+        );
+        assert_eq!(
+            bfs(
+                (0, 0),
+                |&pos| grid_successors(pos, &walls),
+                |&pos| pos == (0, 0)
+            ),
+            Some((vec![(0, 0)], 0))
+        );
+    }
+
+    #[test]
+    fn test_dfs_with_path() {
+        // Inner nodes list their children, leaves have a score
+        let children = [vec![1, 2], vec![3, 4], vec![5], vec![], vec![], vec![]];
+        let scores = [0, 0, 0, 5, 7, 6];
+        let search = |start: usize| {
+            dfs_with_path(
+                start,
+                0,
+                None,
+                |&x| children[x].clone(),
+                |&x| scores[x],
+                |_| u32::MAX,
+                |&x| children[x].is_empty(),
+            )
+        };
+
+        let (score, path) = search(0);
+        assert_eq!(score, 7);
+        assert_eq!(path, Some(vec![0, 1, 4]));
+
+        let (score, path) = search(2);
+        assert_eq!((score, path), (6, Some(vec![2, 5])));
+        let (score, path) = dfs_with_path(
+            0,
+            7,
+            None,
+            |&x| children[x].clone(),
+            |&x| scores[x],
+            |_| u32::MAX,
+            |&x| children[x].is_empty(),
+        );
+        assert_eq!((score, path), (7, None));
+        assert_eq!(
+            dfs(
+                0,
+                |&x| children[x].clone(),
+                |&x| scores[x],
+                |_| u32::MAX,
+                |&x| children[x].is_empty(),
+            ),
+            7
+        );
+    }
+
+    #[test]
+    fn test_dfs_max_iterations() {
+        // Walks around a circle of three positions forever, counting the steps, and can stop at
+        // every position to score it
+        let (score, path, expanded) = depth_first_search(
+            (0, 0, false),
+            0,
+            Some(10),
+            true,
+            |&(pos, steps, _)| [((pos + 1) % 3, steps + 1, false), (pos, steps, true)],
+            |&(pos, _, _)| pos,
+            |_| usize::MAX,
+            |&(_, _, stop)| stop,
+        );
+        assert_eq!(score, 2);
+        assert_eq!(expanded, 10);
+        assert_eq!(
+            path,
+            Some(vec![
+                (0, 0, false),
+                (1, 1, false),
+                (2, 2, false),
+                (2, 2, true)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_grid_index() {
+        let grid = Grid::new((0..6).collect(), 3);
+        assert_eq!((grid.width(), grid.height()), (3, 2));
+        assert_eq!(grid.index(&Point2::new(2, 1)), Some(5));
+        assert_eq!(grid.get(&Point2::new(1, 1)), Some(&4));
+        assert_eq!(grid.pos::<i32>(5), Some(Point2::new(2, 1)));
+        assert_eq!(grid.pos::<u8>(6), None);
+
+        let mut grid = grid;
+        assert_eq!(grid.set(&Point2::new(0, 1), 10), Some(3));
+        assert_eq!(grid.cells(), &[0, 1, 2, 10, 4, 5]);
+    }
+
+    #[test]
+    fn test_grid_bounds() {
+        let mut grid = Grid::filled(3, 2, '.');
+        assert!(grid.in_bounds(&Point2::new(2usize, 1)));
+        assert!(!grid.in_bounds(&Point2::new(3usize, 0)));
+        assert!(!grid.in_bounds(&Point2::new(0, 2)));
+        assert!(!grid.in_bounds(&Point2::new(-1i64, 0)));
+        assert_eq!(grid.get(&Point2::new(0, -1)), None);
+        assert_eq!(grid.set(&Point2::new(5, 5), '#'), None);
+        assert!(grid.cells().iter().all(|&x| x == '.'));
+    }
+
+    #[test]
+    fn test_directions() {
+        assert!(i32::DIRECTIONS_4.iter().all_unique());
+        assert!(i32::DIRECTIONS_4.iter().all(|x| x.abs().sum() == 1));
+
+        assert!(i64::DIRECTIONS_8.iter().all_unique());
+        assert!(i64::DIRECTIONS_8.iter().all(|x| x.abs().max() == 1));
+        assert_eq!(i64::DIRECTIONS_8[..4], i64::DIRECTIONS_4);
+    }
+
+    #[test]
+    fn test_grid_neighbors4() {
+        let grid = Grid::filled(3, 3, 0);
+        assert_eq!(grid.neighbors4(&Point2::new(1, 1)).len(), 4);
+        assert_eq!(
+            grid.neighbors4(&Point2::new(0u32, 0)),
+            vec![Point2::new(1, 0), Point2::new(0, 1)]
+        );
+        assert_eq!(
+            grid.neighbors4(&Point2::new(2i32, 2)),
+            vec![Point2::new(1, 2), Point2::new(2, 1)]
+        );
+        assert!(grid.neighbors4(&Point2::new(3, 0)).is_empty());
+    }
+}